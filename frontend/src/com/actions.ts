@@ -1,4 +1,12 @@
-import { ProjectId, FileId } from './types'
+import {
+  ProjectId,
+  FileId,
+  ExportFormat,
+  ExportOptions,
+  JobId,
+  Metadata,
+  SnapshotId,
+} from './types'
 
 import {
   Client2Server,
@@ -25,6 +33,15 @@ export const fileMsg = (
     msg,
   })
 
+export const createProject = (
+  project_name: string,
+  template?: string,
+): Client2Server => ({
+  type: 'CreateProject',
+  project_name,
+  template,
+})
+
 export const joinProject = (projectId: ProjectId): Client2Server =>
   projectMsg(projectId, {
     type: 'JoinProject',
@@ -33,6 +50,43 @@ export const leaveProject = (projectId: ProjectId): Client2Server =>
   projectMsg(projectId, {
     type: 'LeaveProject',
   })
+export const joinProjectDoc = (projectId: ProjectId): Client2Server =>
+  projectMsg(projectId, {
+    type: 'JoinProjectDoc',
+  })
+export const leaveProjectDoc = (projectId: ProjectId): Client2Server =>
+  projectMsg(projectId, {
+    type: 'LeaveProjectDoc',
+  })
+
+export const createFile = (
+  projectId: ProjectId,
+  file_name: string,
+  folder?: string,
+): Client2Server =>
+  projectMsg(projectId, {
+    type: 'CreateFile',
+    file_name,
+    folder,
+  })
+export const createFolder = (
+  projectId: ProjectId,
+  path: string,
+): Client2Server =>
+  projectMsg(projectId, {
+    type: 'CreateFolder',
+    path,
+  })
+export const moveFile = (
+  projectId: ProjectId,
+  id: FileId,
+  folder?: string,
+): Client2Server =>
+  projectMsg(projectId, {
+    type: 'MoveFile',
+    id,
+    folder,
+  })
 
 export const reorderFiles = (
   projectId: ProjectId,
@@ -45,6 +99,34 @@ export const reorderFiles = (
     new_index,
   })
 
+export const updateMetadata = (
+  projectId: ProjectId,
+  metadata: Metadata,
+): Client2Server =>
+  projectMsg(projectId, {
+    type: 'UpdateMetadata',
+    metadata,
+  })
+
+export const exportProject = (
+  projectId: ProjectId,
+  format: ExportFormat,
+  options?: ExportOptions,
+): Client2Server =>
+  projectMsg(projectId, {
+    type: 'Export',
+    format,
+    options,
+  })
+export const cancelExport = (
+  projectId: ProjectId,
+  job: JobId,
+): Client2Server =>
+  projectMsg(projectId, {
+    type: 'CancelExport',
+    job,
+  })
+
 export const joinFileSource = (
   projectId: ProjectId,
   fileId: FileId,
@@ -74,3 +156,78 @@ export const leaveFileDoc = (
   fileMsg(projectId, fileId, {
     type: 'LeaveFileDoc',
   })
+
+export const enableHistory = (projectId: ProjectId): Client2Server =>
+  projectMsg(projectId, {
+    type: 'EnableHistory',
+  })
+export const commit = (
+  projectId: ProjectId,
+  message: string,
+): Client2Server =>
+  projectMsg(projectId, {
+    type: 'Commit',
+    message,
+  })
+export const getHistory = (projectId: ProjectId): Client2Server =>
+  projectMsg(projectId, {
+    type: 'GetHistory',
+  })
+export const getRevision = (
+  projectId: ProjectId,
+  fileId: FileId,
+  revision: string,
+): Client2Server =>
+  fileMsg(projectId, fileId, {
+    type: 'GetRevision',
+    revision,
+  })
+export const restoreRevision = (
+  projectId: ProjectId,
+  fileId: FileId,
+  revision: string,
+): Client2Server =>
+  fileMsg(projectId, fileId, {
+    type: 'RestoreRevision',
+    revision,
+  })
+
+export const createSnapshot = (
+  projectId: ProjectId,
+  name: string,
+): Client2Server =>
+  projectMsg(projectId, {
+    type: 'CreateSnapshot',
+    name,
+  })
+export const getSnapshots = (projectId: ProjectId): Client2Server =>
+  projectMsg(projectId, {
+    type: 'GetSnapshots',
+  })
+export const diffSnapshot = (
+  projectId: ProjectId,
+  fileId: FileId,
+  snapshot: SnapshotId,
+): Client2Server =>
+  fileMsg(projectId, fileId, {
+    type: 'DiffSnapshot',
+    snapshot,
+  })
+
+export const undo = (projectId: ProjectId, fileId: FileId): Client2Server =>
+  fileMsg(projectId, fileId, {
+    type: 'Undo',
+  })
+export const redo = (projectId: ProjectId, fileId: FileId): Client2Server =>
+  fileMsg(projectId, fileId, {
+    type: 'Redo',
+  })
+export const revertTo = (
+  projectId: ProjectId,
+  fileId: FileId,
+  time: number,
+): Client2Server =>
+  fileMsg(projectId, fileId, {
+    type: 'RevertTo',
+    time,
+  })
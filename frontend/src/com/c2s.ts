@@ -1,9 +1,22 @@
 import { Mapper } from '../util'
-import { ProjectId, FileId } from './types'
+import {
+  ProjectId,
+  FileId,
+  ExportFormat,
+  ExportOptions,
+  JobId,
+  Metadata,
+  SnapshotId,
+} from './types'
 
 export type Client2Server = Mapper<{
   CreateProject: {
     project_name: string
+    template?: string
+  }
+  ImportDirectory: {
+    path: string
+    name?: string
   }
   Project: {
     id: ProjectId
@@ -14,13 +27,42 @@ export type Client2Server = Mapper<{
 export type Client2ServerProject = Mapper<{
   JoinProject: {}
   LeaveProject: {}
+  JoinProjectDoc: {}
+  LeaveProjectDoc: {}
   CreateFile: {
     file_name: string
+    folder?: string
+  }
+  CreateFolder: {
+    path: string
+  }
+  MoveFile: {
+    id: FileId
+    folder?: string
   }
   Reorder: {
     id: FileId
     new_index: number
   }
+  UpdateMetadata: {
+    metadata: Metadata
+  }
+  Export: {
+    format: ExportFormat
+    options?: ExportOptions
+  }
+  CancelExport: {
+    job: JobId
+  }
+  EnableHistory: {}
+  Commit: {
+    message: string
+  }
+  GetHistory: {}
+  CreateSnapshot: {
+    name: string
+  }
+  GetSnapshots: {}
   File: {
     id: FileId
     msg: Client2ServerProjectFile
@@ -33,4 +75,11 @@ export type Client2ServerProjectFile = Mapper<{
   EditFileSource: { contents: string }
   JoinFileDoc: {}
   LeaveFileDoc: {}
+  GetRevision: { revision: string }
+  RestoreRevision: { revision: string }
+  DiffSnapshot: { snapshot: SnapshotId }
+  Undo: {}
+  Redo: {}
+  // Seconds since the unix epoch
+  RevertTo: { time: number }
 }>
@@ -1,5 +1,21 @@
 import { Mapper } from '../util'
-import { ProjectInfo, ProjectId, FileInfo, FileId, Doc } from './types'
+import {
+  ProjectInfo,
+  ProjectId,
+  FileInfo,
+  FileId,
+  Doc,
+  DocEdit,
+  DocSection,
+  Heading,
+  Diagnostic,
+  ExportFormat,
+  JobId,
+  Revision,
+  SnapshotId,
+  SnapshotInfo,
+  DiffHunk,
+} from './types'
 
 export type Lock = Mapper<{
   Unlock: {}
@@ -11,10 +27,16 @@ export type Server2Client = Mapper<{
   Projects: {
     list: ProjectInfo[]
   }
+  Templates: {
+    list: string[]
+  }
   Project: {
     id: ProjectId
     msg: Server2ClientProject
   }
+  Error: {
+    error: string
+  }
 }>
 
 export type Server2ClientProject = Mapper<{
@@ -24,6 +46,49 @@ export type Server2ClientProject = Mapper<{
   UpdateInfo: {
     info: ProjectInfo
   }
+  ProjectDoc: {
+    doc: Doc
+    sections: DocSection[]
+  }
+  ProjectDocPatch: {
+    edits: DocEdit[]
+    sections: DocSection[]
+  }
+  Outline: {
+    outline: Heading[]
+  }
+  ExportStarted: {
+    job: JobId
+    format: ExportFormat
+  }
+  ExportProgress: {
+    job: JobId
+    elapsed_secs: number
+  }
+  ExportComplete: {
+    job: JobId
+    format: ExportFormat
+    url: string
+  }
+  ExportFailed: {
+    job: JobId
+    error: string
+  }
+  ExportCancelled: {
+    job: JobId
+  }
+  History: {
+    revisions: Revision[]
+  }
+  HistoryFailed: {
+    error: string
+  }
+  Snapshots: {
+    list: SnapshotInfo[]
+  }
+  SnapshotFailed: {
+    error: string
+  }
   File: {
     id: FileId
     msg: Server2ClientProjectFile
@@ -34,4 +99,8 @@ export type Server2ClientProjectFile = Mapper<{
   FileLock: { lock: Lock }
   FileSource: { src: string }
   FileDoc: { doc: Doc }
+  DocPatch: { edits: DocEdit[] }
+  Diagnostics: { diagnostics: Diagnostic[] }
+  FileRevision: { revision: string; src: string }
+  SnapshotDiff: { snapshot: SnapshotId; hunks: DiffHunk[] }
 }>
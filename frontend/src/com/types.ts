@@ -1,4 +1,5 @@
 import { Fragment } from '../components/Render'
+import { Mapper } from '../util'
 
 export type SystemTime = {
   secs_since_epoch: number
@@ -10,6 +11,11 @@ export type File = {
   id: FileId
   src: string
   doc: null | Doc
+  diagnostics?: Diagnostic[]
+  // A past version of the source being looked at
+  revision?: { revision: string; src: string }
+  // Changes since a snapshot being looked at
+  snapshotDiff?: { snapshot: SnapshotId; hunks: DiffHunk[] }
 }
 
 export type ProjectFiles = {
@@ -23,9 +29,56 @@ export type ProjectInfo = {
   name: string
   id: ProjectId
   files: FileId[]
+  tree: FileNode[]
+  metadata: Metadata
+  history: boolean
+}
+export type FileNode =
+  | { type: 'File'; id: FileId }
+  | { type: 'Folder'; name: string; path: string; children: FileNode[] }
+  | { type: 'Asset'; name: string; path: string }
+export type Metadata = {
+  title: string | null
+  authors: string[]
+  date: string | null
+  abstract: string | null
+  toc: boolean
 }
 export type FileInfo = {
   name: string
   id: FileId
+  path: string
 }
 export type Doc = Fragment[]
+export type DocSection = { file: FileId; start: number; len: number }
+export type ProjectDoc = { doc: Doc; sections: DocSection[] }
+export type Heading = { file: FileId; level: number; id: string; title: string }
+export type Diagnostic = {
+  severity: 'error' | 'warning'
+  message: string
+  line: number | null
+}
+export type DocEdit = Mapper<{
+  Insert: { index: number; blocks: Doc }
+  Remove: { index: number; count: number }
+  Replace: { index: number; count: number; blocks: Doc }
+}>
+
+export type ExportFormat = 'pdf' | 'html' | 'docx' | 'odt' | 'epub' | 'latex'
+export type ExportOptions = {
+  standalone?: boolean
+  self_contained?: boolean
+  template?: string | null
+}
+export type JobId = { job_id: number }
+export type Revision = { id: string; time: number; message: string }
+export type History = { revisions: Revision[]; error: string | null }
+export type SnapshotId = { snapshot_id: number }
+export type SnapshotInfo = { id: SnapshotId; name: string; time: number }
+export type Snapshots = { list: SnapshotInfo[]; error: string | null }
+export type DiffLine = Mapper<{
+  Context: { text: string }
+  Removed: { text: string }
+  Added: { text: string }
+}>
+export type DiffHunk = { old_line: number; new_line: number; lines: DiffLine[] }
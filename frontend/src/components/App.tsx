@@ -42,12 +42,23 @@ export const App: React.SFC = () => {
           changeRoute={changeRoute}
           connectionStatus={wsStatus.type}
         ></StatusBar>
+        {state.error && (
+          <div className="flex px-2 py-1 bg-red-900 text-red-200">
+            <span className="flex-1">{state.error}</span>
+            <button onClick={() => dispatch({ type: 'DismissError' })}>
+              ✕
+            </button>
+          </div>
+        )}
         <div className="flex flex-1 h-full">
           <Router
             state={state}
             route={route}
             changeRoute={changeRoute}
             send={send}
+            closeFileView={(projectId, fileId) =>
+              dispatch({ type: 'CloseFileView', projectId, fileId })
+            }
             editFile={(projectId, fileId, value) => {
               dispatch({
                 type: 'UpdateFileValue',
@@ -23,10 +23,29 @@ export const Landing: React.SFC<{
         reorder={() => {}}
         footer="+ New Project"
       />
+      <label className="mt-2 cursor-pointer text-gray-500 hover:text-white">
+        Import project from zip
+        <input
+          type="file"
+          accept=".zip"
+          className="hidden"
+          onChange={e => importArchive(e.target.files)}
+        />
+      </label>
     </div>
   </div>
 )
 
+// The project list is sent by the server once the project is imported
+const importArchive = (files: FileList | null) => {
+  if (!files || files.length == 0) return
+  const file = files[0]
+  const name = encodeURIComponent(file.name.replace(/\.zip$/, ''))
+  fetch(`/import?name=${name}`, { method: 'POST', body: file }).then(res => {
+    if (!res.ok) res.text().then(e => console.error('import failed', e))
+  })
+}
+
 const ProjectListItem: React.SFC<{
   info: ProjectInfo
   send: (msg: Client2Server) => any
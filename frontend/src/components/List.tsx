@@ -10,6 +10,7 @@ export function List<T>(
     isSelected: (t: T, i: number) => boolean
     reorder: (t: T, from: number, to: number) => any
     footer?: React.ReactNode
+    add?: () => any
   },
 ) {
   const [drag, setDrag] = React.useState<null | number>(null)
@@ -24,6 +25,7 @@ export function List<T>(
           href="/"
           onClick={e => {
             e.preventDefault()
+            if (props.add) props.add()
           }}
         >
           +
@@ -78,6 +80,7 @@ export function List<T>(
             className="flex p-2 bg-gray-900 text-gray-500 hover:bg-black hover:text-white"
             onClick={e => {
               e.preventDefault()
+              if (props.add) props.add()
             }}
           >
             {props.footer}
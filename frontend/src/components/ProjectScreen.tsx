@@ -1,4 +1,5 @@
 import * as React from 'react'
+import * as monaco from 'monaco-editor'
 import { editor } from 'monaco-editor'
 import MonacoEditor from 'react-monaco-editor'
 import '../editorSetup'
@@ -11,6 +12,14 @@ import {
   FileId,
   ProjectId,
   Doc,
+  Metadata,
+  Diagnostic,
+  Heading,
+  FileNode,
+  History,
+  Snapshots,
+  SnapshotId,
+  DiffHunk,
 } from '../com/types'
 import { Client2Server, Client2ServerProjectFile } from '../com/c2s'
 import { getFileName } from '../state'
@@ -21,8 +30,20 @@ import {
   joinProject,
   leaveProject,
   reorderFiles,
+  createFile,
   leaveFileDoc,
   joinFileDoc,
+  enableHistory,
+  commit,
+  getHistory,
+  getRevision,
+  restoreRevision,
+  createSnapshot,
+  getSnapshots,
+  diffSnapshot,
+  undo,
+  redo,
+  revertTo,
 } from '../com/actions'
 import { Send } from '../com/socket'
 
@@ -39,12 +60,27 @@ editor.defineTheme('darko', {
 export const ProjectScreen: React.SFC<{
   fileInfos: { [fileId: number]: FileInfo }
   files: ProjectFiles
+  outline: Heading[]
+  history?: History
+  snapshots?: Snapshots
   send: Send
   selectedFile: FileId | null
   editFile: (id: FileId, value: string) => any
+  closeFileView: (id: FileId) => any
   selectConfig: () => any
   selectFile: (id: FileId) => any
-}> = ({ fileInfos, files, send, selectedFile, selectFile, editFile }) => {
+}> = ({
+  fileInfos,
+  files,
+  outline,
+  history,
+  snapshots,
+  send,
+  selectedFile,
+  selectFile,
+  editFile,
+  closeFileView,
+}) => {
   const info = React.useContext(ProjectInfoContext)
 
   if (!info) return <div>Loading...</div>
@@ -59,19 +95,29 @@ export const ProjectScreen: React.SFC<{
   return (
     <div className="flex flex-1 h-full">
       <div className="flex flex-col my-2 ml-2 mr-1 shadow-xl bg-gray-800 w-40 rounded overflow-hidden">
-        <List
-          items={info.files}
+        <FileTree
           title="Files"
-          keyer={file => file.file_id}
-          render={file => getFileName(files, info.id, file) || '???'}
-          select={selectFile}
-          isSelected={file =>
-            (selectedFile && selectedFile.file_id == file.file_id) || false
-          }
-          reorder={(file, _, to) => {
-            if (info) send(reorderFiles(info.id, file, to))
-          }}
-          footer="+ New File"
+          nodes={info.tree}
+          files={files}
+          send={send}
+          selectedFile={selectedFile}
+          selectFile={selectFile}
+        />
+        <a
+          href={`/download/${info.id.project_id}.zip`}
+          className="p-2 text-sm text-gray-500 hover:text-white"
+        >
+          Download project
+        </a>
+        <HistoryPanel
+          history={history}
+          send={send}
+          selectedFile={selectedFile}
+        />
+        <SnapshotPanel
+          snapshots={snapshots}
+          send={send}
+          selectedFile={selectedFile}
         />
       </div>
       <div className="flex flex-1 justify-evenly">
@@ -81,11 +127,28 @@ export const ProjectScreen: React.SFC<{
             f || !animate ? 'w-1/2 mx-2' : 'w-0 mx-0 opacity-0'
           } flex-col max-w-3xl shadow-xl bg-gray-800 my-2`}
         >
+          {selectedFile && <EditToolbar fileId={selectedFile} send={send} />}
           <div className="flex flex-1 relative">
+            {selectedFile && f && f.revision && (
+              <RevisionView
+                fileId={selectedFile}
+                revision={f.revision}
+                send={send}
+                close={() => closeFileView(selectedFile)}
+              />
+            )}
+            {selectedFile && f && f.snapshotDiff && (
+              <SnapshotDiffView
+                name={snapshotName(snapshots, f.snapshotDiff.snapshot)}
+                hunks={f.snapshotDiff.hunks}
+                close={() => closeFileView(selectedFile)}
+              />
+            )}
             {selectedFile && (
               <Editor
                 fileId={selectedFile}
                 src={f ? f.src : ''}
+                diagnostics={(f && f.diagnostics) || []}
                 onChange={value => {
                   if (selectedFile) {
                     editFile(selectedFile, value)
@@ -99,6 +162,10 @@ export const ProjectScreen: React.SFC<{
         <div className="flex flex-1 max-w-3xl mx-2 shadow-xl bg-gray-800 overflow-y-auto p-4 my-2 rounded">
           <div className="flex flex-1 relative">
             <div className="flex flex-1 markdown absolute inset-0">
+              {info && <TitleBlock metadata={info.metadata} />}
+              {info && info.metadata.toc && (
+                <TableOfContents outline={outline} />
+              )}
               {info &&
                 info.files.map(fileId => {
                   const projectId = info.id
@@ -148,14 +215,390 @@ const DocListener: React.SFC<{
   )
 }
 
+const TitleBlock: React.SFC<{ metadata: Metadata }> = ({ metadata }) => {
+  const { title, authors, date } = metadata
+  if (!title && authors.length == 0 && !date && !metadata.abstract) return null
+
+  return (
+    <header className="title-block">
+      {metadata.title && <h1 className="title">{metadata.title}</h1>}
+      {metadata.authors.length > 0 && (
+        <p className="authors">{metadata.authors.join(', ')}</p>
+      )}
+      {metadata.date && <p className="date">{metadata.date}</p>}
+      {metadata.abstract &&
+        metadata.abstract
+          .split('\n\n')
+          .map((p, i) => <p key={i} className="abstract">{p}</p>)}
+    </header>
+  )
+}
+
+const FileTree: React.SFC<{
+  title: string
+  folder?: string
+  nodes: FileNode[]
+  files: ProjectFiles
+  send: Send
+  selectedFile: FileId | null
+  selectFile: (id: FileId) => any
+}> = ({ title, folder, nodes, files, send, selectedFile, selectFile }) => {
+  const info = React.useContext(ProjectInfoContext)!
+
+  // The list holds the files of this folder, which is also what reorder
+  // indices count
+  const fileIds: FileId[] = []
+  nodes.forEach(node => {
+    if (node.type == 'File') fileIds.push(node.id)
+  })
+
+  return (
+    <>
+      <List
+        items={fileIds}
+        title={title}
+        keyer={file => file.file_id}
+        render={file => getFileName(files, info.id, file) || '???'}
+        select={selectFile}
+        isSelected={file =>
+          (selectedFile && selectedFile.file_id == file.file_id) || false
+        }
+        reorder={(file, _, to) => send(reorderFiles(info.id, file, to))}
+        add={() => {
+          const name = window.prompt('File name')
+          if (name) send(createFile(info.id, name, folder))
+        }}
+        footer="+ New File"
+      />
+      <AssetList
+        projectId={info.id}
+        folder={folder}
+        assets={nodes.filter(node => node.type == 'Asset')}
+      />
+      {nodes.map(node =>
+        node.type == 'Folder' ? (
+          <div key={node.path} className="flex flex-col ml-2 mt-1">
+            <FileTree
+              title={node.name}
+              folder={node.path}
+              nodes={node.children}
+              files={files}
+              send={send}
+              selectedFile={selectedFile}
+              selectFile={selectFile}
+            />
+          </div>
+        ) : null,
+      )}
+    </>
+  )
+}
+
+const AssetList: React.SFC<{
+  projectId: ProjectId
+  folder?: string
+  assets: FileNode[]
+}> = ({ projectId, folder, assets }) => {
+  const assetUrl = (path: string) =>
+    `/assets/${projectId.project_id}/${path
+      .split('/')
+      .map(encodeURIComponent)
+      .join('/')}`
+
+  // The file tree is updated by the server once the upload is stored
+  const upload = (files: FileList | null) => {
+    if (!files) return
+    Array.from(files).forEach(file => {
+      const path = folder ? `${folder}/${file.name}` : file.name
+      fetch(assetUrl(path), { method: 'POST', body: file }).then(res => {
+        if (!res.ok) res.text().then(e => console.error('upload failed', e))
+      })
+    })
+  }
+
+  return (
+    <div className="flex flex-col px-2 py-1 text-gray-500 text-sm">
+      {assets.map(asset =>
+        asset.type == 'Asset' ? (
+          <a
+            key={asset.path}
+            href={assetUrl(asset.path)}
+            target="_blank"
+            className="hover:text-white truncate"
+          >
+            {asset.name}
+          </a>
+        ) : null,
+      )}
+      <label className="cursor-pointer hover:text-white">
+        + Upload asset
+        <input
+          type="file"
+          multiple
+          className="hidden"
+          onChange={e => upload(e.target.files)}
+        />
+      </label>
+    </div>
+  )
+}
+
+// Undo lives on the server, so it works across tabs and for the edits of
+// others
+const EditToolbar: React.SFC<{ fileId: FileId; send: Send }> = ({
+  fileId,
+  send,
+}) => {
+  const info = React.useContext(ProjectInfoContext)!
+
+  return (
+    <div className="flex p-2 text-sm text-gray-500">
+      <button
+        className="mr-2 hover:text-white"
+        onClick={() => send(undo(info.id, fileId))}
+      >
+        Undo
+      </button>
+      <button
+        className="mr-2 hover:text-white"
+        onClick={() => send(redo(info.id, fileId))}
+      >
+        Redo
+      </button>
+      <button
+        className="hover:text-white"
+        onClick={() => {
+          const minutes = Number(
+            window.prompt('Revert to how many minutes ago?'),
+          )
+          if (minutes > 0) {
+            const time = Math.floor(Date.now() / 1000) - minutes * 60
+            send(revertTo(info.id, fileId, time))
+          }
+        }}
+      >
+        Revert…
+      </button>
+    </div>
+  )
+}
+
+const HistoryPanel: React.SFC<{
+  history?: History
+  snapshots?: Snapshots
+  send: Send
+  selectedFile: FileId | null
+}> = ({ history, send, selectedFile }) => {
+  const info = React.useContext(ProjectInfoContext)!
+
+  React.useEffect(() => {
+    if (info.history) send(getHistory(info.id))
+  }, [info.id.project_id, info.history])
+
+  if (!info.history) {
+    return (
+      <button
+        className="p-2 text-sm text-left text-gray-500 hover:text-white"
+        onClick={() => send(enableHistory(info.id))}
+      >
+        Keep history
+      </button>
+    )
+  }
+
+  return (
+    <div className="flex flex-col p-2 text-gray-500 text-sm overflow-y-auto">
+      <button
+        className="text-left hover:text-white"
+        onClick={() => {
+          const message = window.prompt('Describe the changes')
+          if (message) send(commit(info.id, message))
+        }}
+      >
+        + Commit
+      </button>
+      {history && history.error && (
+        <div className="text-red-400">{history.error}</div>
+      )}
+      {history &&
+        history.revisions.map(revision => (
+          <button
+            key={revision.id}
+            className="text-left truncate hover:text-white"
+            title={new Date(revision.time * 1000).toLocaleString()}
+            disabled={!selectedFile}
+            onClick={() => {
+              if (selectedFile)
+                send(getRevision(info.id, selectedFile, revision.id))
+            }}
+          >
+            {revision.message}
+          </button>
+        ))}
+    </div>
+  )
+}
+
+// Shown over the editor, as the past source is only looked at until it is
+// restored
+const RevisionView: React.SFC<{
+  fileId: FileId
+  revision: { revision: string; src: string }
+  send: Send
+  close: () => any
+}> = ({ fileId, revision, send, close }) => {
+  const info = React.useContext(ProjectInfoContext)!
+
+  return (
+    <div className="flex flex-col absolute inset-0 z-10 bg-gray-800">
+      <div className="flex p-2 text-sm text-gray-500">
+        <span className="flex-1">
+          Revision {revision.revision.slice(0, 7)}
+        </span>
+        <button
+          className="mr-2 hover:text-white"
+          onClick={() => {
+            send(restoreRevision(info.id, fileId, revision.revision))
+            close()
+          }}
+        >
+          Restore
+        </button>
+        <button className="hover:text-white" onClick={close}>
+          Close
+        </button>
+      </div>
+      <pre className="flex-1 p-2 overflow-auto text-gray-300 whitespace-pre-wrap">
+        {revision.src}
+      </pre>
+    </div>
+  )
+}
+
+const SnapshotPanel: React.SFC<{
+  snapshots?: Snapshots
+  send: Send
+  selectedFile: FileId | null
+}> = ({ snapshots, send, selectedFile }) => {
+  const info = React.useContext(ProjectInfoContext)!
+
+  React.useEffect(() => {
+    send(getSnapshots(info.id))
+  }, [info.id.project_id])
+
+  return (
+    <div className="flex flex-col p-2 text-gray-500 text-sm overflow-y-auto">
+      <button
+        className="text-left hover:text-white"
+        onClick={() => {
+          const name = window.prompt('Snapshot name')
+          if (name) send(createSnapshot(info.id, name))
+        }}
+      >
+        + Snapshot
+      </button>
+      {snapshots && snapshots.error && (
+        <div className="text-red-400">{snapshots.error}</div>
+      )}
+      {snapshots &&
+        snapshots.list.map(snapshot => (
+          <button
+            key={snapshot.id.snapshot_id}
+            className="text-left truncate hover:text-white"
+            title={new Date(snapshot.time * 1000).toLocaleString()}
+            disabled={!selectedFile}
+            onClick={() => {
+              if (selectedFile)
+                send(diffSnapshot(info.id, selectedFile, snapshot.id))
+            }}
+          >
+            {snapshot.name}
+          </button>
+        ))}
+    </div>
+  )
+}
+
+const snapshotName = (snapshots: Snapshots | undefined, id: SnapshotId) => {
+  const snapshot =
+    snapshots &&
+    snapshots.list.filter(s => s.id.snapshot_id == id.snapshot_id)[0]
+  return snapshot ? snapshot.name : '???'
+}
+
+const SnapshotDiffView: React.SFC<{
+  name: string
+  hunks: DiffHunk[]
+  close: () => any
+}> = ({ name, hunks, close }) => (
+  <div className="flex flex-col absolute inset-0 z-10 bg-gray-800">
+    <div className="flex p-2 text-sm text-gray-500">
+      <span className="flex-1">Changes since {name}</span>
+      <button className="hover:text-white" onClick={close}>
+        Close
+      </button>
+    </div>
+    <div className="flex-1 p-2 overflow-auto font-mono text-sm">
+      {hunks.length == 0 && <div className="text-gray-500">No changes</div>}
+      {hunks.map((hunk, i) => (
+        <div key={i} className="mb-4">
+          <div className="text-gray-500">
+            @@ -{hunk.old_line} +{hunk.new_line} @@
+          </div>
+          {hunk.lines.map((line, j) => (
+            <div
+              key={j}
+              className={`whitespace-pre-wrap ${
+                line.type == 'Added'
+                  ? 'text-green-400'
+                  : line.type == 'Removed'
+                  ? 'text-red-400'
+                  : 'text-gray-300'
+              }`}
+            >
+              {line.type == 'Added' ? '+' : line.type == 'Removed' ? '-' : ' '}
+              {line.text}
+            </div>
+          ))}
+        </div>
+      ))}
+    </div>
+  </div>
+)
+
+// Mirrors the table of contents the server puts in exported documents
+const TableOfContents: React.SFC<{ outline: Heading[] }> = ({ outline }) => {
+  if (outline.length == 0) return null
+
+  const minLevel = Math.min(...outline.map(h => h.level))
+
+  return (
+    <nav className="toc">
+      {outline.map((heading, i) => (
+        <div
+          key={i}
+          style={{ paddingLeft: `${heading.level - minLevel}rem` }}
+        >
+          {heading.id ? (
+            <a href={`#${heading.id}`}>{heading.title}</a>
+          ) : (
+            heading.title
+          )}
+        </div>
+      ))}
+    </nav>
+  )
+}
+
 export const ProjectInfoContext = React.createContext<ProjectInfo | null>(null)
 
 const Editor: React.FC<{
   fileId: FileId
   src: string
+  diagnostics: Diagnostic[]
   onChange: (value: string) => any
   send: Send
-}> = ({ fileId, src, onChange, send }) => {
+}> = ({ fileId, src, diagnostics, onChange, send }) => {
   const info = React.useContext(ProjectInfoContext)!
 
   React.useEffect(() => {
@@ -199,6 +642,30 @@ const Editor: React.FC<{
     }
   }, [editor, fileId.file_id])
 
+  React.useEffect(() => {
+    const model = editor && editor.getModel()
+    if (!model) return
+    // `editor` is shadowed by the editor instance here
+    monaco.editor.setModelMarkers(
+      model,
+      'riport',
+      diagnostics.map(d => {
+        const line = d.line || 1
+        return {
+          severity:
+            d.severity == 'error'
+              ? monaco.MarkerSeverity.Error
+              : monaco.MarkerSeverity.Warning,
+          message: d.message,
+          startLineNumber: line,
+          startColumn: 1,
+          endLineNumber: line,
+          endColumn: model.getLineMaxColumn(line),
+        }
+      }),
+    )
+  }, [editor, diagnostics])
+
   React.useEffect(() => {
     const resize = () => {
       if (editor) editor.layout()
@@ -32,6 +32,8 @@ export type Fragment =
       c: [['', [], []], Fragment[], [string, '']]
     }
   | { t: 'Para'; c: Fragment[] }
+  | { t: 'Plain'; c: Fragment[] }
+  | { t: 'BulletList'; c: Fragment[][] }
   | {
       t: 'BlockQuote'
       c: Fragment[]
@@ -43,6 +45,9 @@ export type Fragment =
   | { t: 'Code'; c: [['', [], []], string] }
   | { t: 'Math'; c: [{ t: 'InlineMath' | 'DisplayMath' }, string] }
   | { t: 'CodeBlock'; c: [[string, string], string] }
+  | { t: 'Cite'; c: [{}[], Fragment[]] }
+  | { t: 'Span'; c: [[string, string[], [string, string][]], Fragment[]] }
+  | { t: 'Div'; c: [[string, string[], [string, string][]], Fragment[]] }
 
 export const Render: React.SFC<{
   staticUrl: (src: string) => string
@@ -51,13 +56,23 @@ export const Render: React.SFC<{
 }> = ({ src, fileId, staticUrl }) => {
   const propagate = (c: Fragment[]) =>
     c.map((b, i) => (
-      <Render key={i} fileId={fileId} staticUrl={staticUrl} src={b} />
+      <MemoRender key={i} fileId={fileId} staticUrl={staticUrl} src={b} />
     ))
 
   if (Array.isArray(src)) {
     return <>{propagate(src)}</>
   } else if (src.t == 'Para') {
     return <p>{propagate(src.c)}</p>
+  } else if (src.t == 'Plain') {
+    return <>{propagate(src.c)}</>
+  } else if (src.t == 'BulletList') {
+    return (
+      <ul>
+        {src.c.map((item, i) => (
+          <li key={i}>{propagate(item)}</li>
+        ))}
+      </ul>
+    )
   } else if (src.t == 'BlockQuote') {
     return (
       <blockquote className="pl-2 border-l italic">
@@ -67,19 +82,21 @@ export const Render: React.SFC<{
   } else if (src.t == 'Str') {
     return <>{src.c}</>
   } else if (src.t == 'Header') {
+    // Headers keep their ids so the table of contents can link to them
+    const id = src.c[1][0] || undefined
     switch (src.c[0]) {
       case 1:
-        return <h1>{propagate(src.c[2])}</h1>
+        return <h1 id={id}>{propagate(src.c[2])}</h1>
       case 2:
-        return <h2>{propagate(src.c[2])}</h2>
+        return <h2 id={id}>{propagate(src.c[2])}</h2>
       case 3:
-        return <h3>{propagate(src.c[2])}</h3>
+        return <h3 id={id}>{propagate(src.c[2])}</h3>
       case 4:
-        return <h4>{propagate(src.c[2])}</h4>
+        return <h4 id={id}>{propagate(src.c[2])}</h4>
       case 5:
-        return <h5>{propagate(src.c[2])}</h5>
+        return <h5 id={id}>{propagate(src.c[2])}</h5>
       case 6:
-        return <h6>{propagate(src.c[2])}</h6>
+        return <h6 id={id}>{propagate(src.c[2])}</h6>
       default:
         return (
           <p>
@@ -101,6 +118,20 @@ export const Render: React.SFC<{
     return <b>{propagate(src.c)}</b>
   } else if (src.t == 'Link') {
     return <a href={src.c[2][0]}>{propagate(src.c[1])}</a>
+  } else if (src.t == 'Cite') {
+    return <cite>{propagate(src.c[1])}</cite>
+  } else if (src.t == 'Span') {
+    return (
+      <span id={src.c[0][0] || undefined} className={src.c[0][1].join(' ')}>
+        {propagate(src.c[1])}
+      </span>
+    )
+  } else if (src.t == 'Div') {
+    return (
+      <div id={src.c[0][0] || undefined} className={src.c[0][1].join(' ')}>
+        {propagate(src.c[1])}
+      </div>
+    )
   } else if (src.t == 'Code') {
     return <code className="inline">{src.c[1]}</code>
   } else if (src.t == 'Image') {
@@ -163,3 +194,11 @@ export const Render: React.SFC<{
     return <>{JSON.stringify(src)}</>
   }
 }
+
+// Doc patches keep unchanged fragments by reference, so only changed
+// fragments are rendered again
+const MemoRender = React.memo(
+  Render,
+  (prev, next) =>
+    prev.src === next.src && prev.fileId.file_id == next.fileId.file_id,
+)
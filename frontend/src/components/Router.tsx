@@ -11,7 +11,8 @@ export const Router: React.SFC<{
   changeRoute: (route: Routes) => any
   send: (msg: Client2Server) => any
   editFile: (projectId: ProjectId, fileId: FileId, value: string) => any
-}> = ({ state, route, changeRoute, send, editFile }) => {
+  closeFileView: (projectId: ProjectId, fileId: FileId) => any
+}> = ({ state, route, changeRoute, send, editFile, closeFileView }) => {
   switch (route.name) {
     case 'landing': {
       return (
@@ -30,6 +31,9 @@ export const Router: React.SFC<{
           <ProjectScreen
             fileInfos={state.projectFileInfos[route.id.project_id] || {}}
             files={state.projectFiles[route.id.project_id]}
+            outline={state.projectOutlines[route.id.project_id] || []}
+            history={state.projectHistories[route.id.project_id]}
+            snapshots={state.projectSnapshots[route.id.project_id]}
             send={send}
             selectConfig={() => {
               changeRoute({ name: 'project', id: route.id, config: true })
@@ -41,6 +45,7 @@ export const Router: React.SFC<{
             editFile={(id, value) => {
               editFile(route.id, id, value)
             }}
+            closeFileView={id => closeFileView(route.id, id)}
           />
         </ProjectInfoContext.Provider>
       )
@@ -4,6 +4,12 @@ import {
   FileInfo,
   ProjectFiles,
   FileId,
+  Doc,
+  DocEdit,
+  ProjectDoc,
+  Heading,
+  History,
+  Snapshots,
 } from './com/types'
 import {
   Server2Client,
@@ -14,8 +20,14 @@ import {
 export type State = {
   route: Routes
   projects: ProjectInfo[]
+  templates: string[]
+  error: string | null
   projectFileInfos: { [project_id: number]: FileInfo[] }
   projectFiles: { [project_id: number]: ProjectFiles }
+  projectDocs: { [project_id: number]: ProjectDoc }
+  projectOutlines: { [project_id: number]: Heading[] }
+  projectHistories: { [project_id: number]: History }
+  projectSnapshots: { [project_id: number]: Snapshots }
 }
 
 export type PathSegment = { name: string; route: Routes }
@@ -42,8 +54,14 @@ export type Routes =
 export const initialState: State = {
   route: { name: 'landing' },
   projects: [],
+  templates: [],
+  error: null,
   projectFileInfos: {},
   projectFiles: {},
+  projectDocs: {},
+  projectOutlines: {},
+  projectHistories: {},
+  projectSnapshots: {},
 }
 
 export type Action =
@@ -55,6 +73,8 @@ export type Action =
       fileId: FileId
       value: string
     }
+  | { type: 'CloseFileView'; projectId: ProjectId; fileId: FileId }
+  | { type: 'DismissError' }
 
 export const reducer: React.Reducer<State, Action> = (state, action) => {
   switch (action.type) {
@@ -65,6 +85,29 @@ export const reducer: React.Reducer<State, Action> = (state, action) => {
     case 'Server': {
       return handleServerMsg(state, action.msg)
     }
+    case 'DismissError': {
+      return { ...state, error: null }
+    }
+    case 'CloseFileView': {
+      const projectFiles = state.projectFiles[action.projectId.project_id]
+      const f = projectFiles && projectFiles[action.fileId.file_id]
+      if (!f) return state
+
+      return {
+        ...state,
+        projectFiles: {
+          ...state.projectFiles,
+          [action.projectId.project_id]: {
+            ...projectFiles,
+            [action.fileId.file_id]: {
+              ...f,
+              revision: undefined,
+              snapshotDiff: undefined,
+            },
+          },
+        },
+      }
+    }
     case 'UpdateFileValue': {
       const projectFiles = state.projectFiles[action.projectId.project_id] || {}
       const f = projectFiles[action.fileId.file_id] || {
@@ -96,10 +139,16 @@ const handleServerMsg = (state: State, msg: Server2Client): State => {
     case 'Projects': {
       return { ...state, projects: msg.list }
     }
+    case 'Templates': {
+      return { ...state, templates: msg.list }
+    }
     case 'Project': {
       const { id, msg: msg2 } = msg
       return handleServerProjectMsg(state, id, msg2)
     }
+    case 'Error': {
+      return { ...state, error: msg.error }
+    }
     default: {
       console.log('unhandled', msg)
       return state
@@ -125,6 +174,84 @@ const handleServerProjectMsg = (
         }),
       }
     }
+    case 'ProjectDoc': {
+      return {
+        ...state,
+        projectDocs: {
+          ...state.projectDocs,
+          [projectId.project_id]: { doc: msg.doc, sections: msg.sections },
+        },
+      }
+    }
+    case 'ProjectDocPatch': {
+      const projectDoc = state.projectDocs[projectId.project_id]
+      // Patches are relative to a doc we have not received yet
+      if (!projectDoc) return state
+
+      return {
+        ...state,
+        projectDocs: {
+          ...state.projectDocs,
+          [projectId.project_id]: {
+            doc: applyDocEdits(projectDoc.doc, msg.edits),
+            sections: msg.sections,
+          },
+        },
+      }
+    }
+    case 'Outline': {
+      return {
+        ...state,
+        projectOutlines: {
+          ...state.projectOutlines,
+          [projectId.project_id]: msg.outline,
+        },
+      }
+    }
+    case 'History': {
+      return {
+        ...state,
+        projectHistories: {
+          ...state.projectHistories,
+          [projectId.project_id]: { revisions: msg.revisions, error: null },
+        },
+      }
+    }
+    case 'HistoryFailed': {
+      const history = state.projectHistories[projectId.project_id]
+      return {
+        ...state,
+        projectHistories: {
+          ...state.projectHistories,
+          [projectId.project_id]: {
+            revisions: history ? history.revisions : [],
+            error: msg.error,
+          },
+        },
+      }
+    }
+    case 'Snapshots': {
+      return {
+        ...state,
+        projectSnapshots: {
+          ...state.projectSnapshots,
+          [projectId.project_id]: { list: msg.list, error: null },
+        },
+      }
+    }
+    case 'SnapshotFailed': {
+      const snapshots = state.projectSnapshots[projectId.project_id]
+      return {
+        ...state,
+        projectSnapshots: {
+          ...state.projectSnapshots,
+          [projectId.project_id]: {
+            list: snapshots ? snapshots.list : [],
+            error: msg.error,
+          },
+        },
+      }
+    }
     case 'Files': {
       return {
         ...state,
@@ -214,6 +341,85 @@ const handleServerProjectFileMsg = (
         },
       }
     }
+    case 'DocPatch': {
+      const projectFiles = state.projectFiles[projectId.project_id] || {}
+      const f = projectFiles[fileId.file_id]
+      // Patches are relative to a doc we have not received yet
+      if (!f || !f.doc) return state
+
+      return {
+        ...state,
+        projectFiles: {
+          ...state.projectFiles,
+          [projectId.project_id]: {
+            ...projectFiles,
+            [fileId.file_id]: {
+              ...f,
+              doc: applyDocEdits(f.doc, msg.edits),
+            },
+          },
+        },
+      }
+    }
+    case 'Diagnostics': {
+      const projectFiles = state.projectFiles[projectId.project_id] || {}
+      const f = projectFiles[fileId.file_id]
+      if (!f) return state
+
+      return {
+        ...state,
+        projectFiles: {
+          ...state.projectFiles,
+          [projectId.project_id]: {
+            ...projectFiles,
+            [fileId.file_id]: {
+              ...f,
+              diagnostics: msg.diagnostics,
+            },
+          },
+        },
+      }
+    }
+    case 'FileRevision': {
+      const projectFiles = state.projectFiles[projectId.project_id] || {}
+      const f = projectFiles[fileId.file_id]
+      if (!f) return state
+
+      return {
+        ...state,
+        projectFiles: {
+          ...state.projectFiles,
+          [projectId.project_id]: {
+            ...projectFiles,
+            [fileId.file_id]: {
+              ...f,
+              revision: { revision: msg.revision, src: msg.src },
+              snapshotDiff: undefined,
+            },
+          },
+        },
+      }
+    }
+    case 'SnapshotDiff': {
+      const projectFiles = state.projectFiles[projectId.project_id] || {}
+      const f = projectFiles[fileId.file_id]
+      if (!f) return state
+
+      return {
+        ...state,
+        projectFiles: {
+          ...state.projectFiles,
+          [projectId.project_id]: {
+            ...projectFiles,
+            [fileId.file_id]: {
+              ...f,
+              revision: undefined,
+              snapshotDiff: { snapshot: msg.snapshot, hunks: msg.hunks },
+            },
+          },
+        },
+      }
+    }
     default: {
       console.log('unhandled file', msg)
       return state
@@ -221,6 +427,29 @@ const handleServerProjectFileMsg = (
   }
 }
 
+export const applyDocEdits = (doc: Doc, edits: DocEdit[]): Doc =>
+  edits.reduce((doc, edit) => {
+    switch (edit.type) {
+      case 'Insert':
+        return [
+          ...doc.slice(0, edit.index),
+          ...edit.blocks,
+          ...doc.slice(edit.index),
+        ]
+      case 'Remove':
+        return [
+          ...doc.slice(0, edit.index),
+          ...doc.slice(edit.index + edit.count),
+        ]
+      case 'Replace':
+        return [
+          ...doc.slice(0, edit.index),
+          ...edit.blocks,
+          ...doc.slice(edit.index + edit.count),
+        ]
+    }
+  }, doc)
+
 export const findProjectInfo = (
   state: State,
   id: ProjectId,
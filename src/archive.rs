@@ -0,0 +1,129 @@
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// The contents of a file going into an archive.
+pub enum Entry {
+    Data(Vec<u8>),
+    /// A file on disk, read when the archive is written.
+    File(PathBuf),
+}
+
+/// The name of `path` inside an archive, which always uses forward slashes.
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Writes the entries to a zip archive.
+pub fn zip(entries: Vec<(PathBuf, Entry)>) -> io::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (path, entry) in entries {
+        let data = match entry {
+            Entry::Data(data) => data,
+            Entry::File(path) => fs::read(path)?,
+        };
+        zip.start_file(entry_name(&path), options)?;
+        zip.write_all(&data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Extracts a zip archive into `dir`. When everything in the archive is in
+/// one folder, as in an archive of a downloaded project, the contents of the
+/// folder are extracted instead.
+pub fn unzip(data: &[u8], dir: &Path) -> io::Result<()> {
+    let mut zip = ZipArchive::new(Cursor::new(data))?;
+
+    let mut files = vec![];
+    for i in 0..zip.len() {
+        let file = zip.by_index(i)?;
+        // Directories are created along with the files in them
+        if !file.name().ends_with('/') {
+            // Sanitized, so nothing is extracted outside of `dir`
+            files.push((i, file.sanitized_name()));
+        }
+    }
+
+    let first = |path: &PathBuf| path.components().next().map(|c| c.as_os_str().to_owned());
+    let prefix = files.first().and_then(|(_, path)| first(path));
+    let in_one_folder = prefix.is_some()
+        && files
+            .iter()
+            .all(|(_, path)| path.components().count() > 1 && first(path) == prefix);
+
+    fs::create_dir_all(dir)?;
+    for (i, path) in files {
+        let path = match &prefix {
+            Some(prefix) if in_one_folder => path.strip_prefix(prefix).unwrap().to_path_buf(),
+            _ => path,
+        };
+        let dest = dir.join(path);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = zip.by_index(i)?;
+        io::copy(&mut file, &mut fs::File::create(dest)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    fn archive(paths: &[&str]) -> Vec<u8> {
+        zip(paths
+            .iter()
+            .map(|path| (PathBuf::from(path), Entry::Data(path.as_bytes().to_vec())))
+            .collect())
+        .unwrap()
+    }
+
+    /// Unzips an archive of `paths`, checking each file ends up at the path
+    /// it is mapped to.
+    fn check_unzip(paths: &[(&str, &str)]) {
+        let dir = TempDir::new("unzip").unwrap();
+        let data = archive(&paths.iter().map(|(path, _)| *path).collect::<Vec<_>>());
+        unzip(&data, dir.path()).unwrap();
+        for (path, dest) in paths {
+            assert_eq!(
+                fs::read_to_string(dir.path().join(dest)).unwrap(),
+                *path,
+                "{} was not extracted to {}",
+                path,
+                dest
+            );
+        }
+    }
+
+    #[test]
+    fn folder_of_archive_is_stripped() {
+        check_unzip(&[
+            ("report/index.md", "index.md"),
+            ("report/img/plot.png", "img/plot.png"),
+        ]);
+    }
+
+    #[test]
+    fn several_folders_are_kept() {
+        check_unzip(&[
+            ("report/index.md", "report/index.md"),
+            ("img/plot.png", "img/plot.png"),
+        ]);
+        check_unzip(&[("index.md", "index.md"), ("img/plot.png", "img/plot.png")]);
+    }
+
+    #[test]
+    fn single_file_is_kept() {
+        check_unzip(&[("index.md", "index.md")]);
+    }
+}
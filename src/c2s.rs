@@ -1,13 +1,26 @@
 use serde::Deserialize;
 
+use std::path::PathBuf;
+
+use crate::export::{ExportFormat, ExportOptions, JobId};
 use crate::project::file::FileId;
+use crate::project::metadata::Metadata;
 use crate::project::ProjectId;
+use crate::snapshot::SnapshotId;
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum Client2Server {
     CreateProject {
         project_name: String,
+        #[serde(default)]
+        template: Option<String>,
+    },
+    /// Imports a directory of markdown files on the server as a project.
+    ImportDirectory {
+        path: PathBuf,
+        #[serde(default)]
+        name: Option<String>,
     },
     Project {
         id: ProjectId,
@@ -20,13 +33,46 @@ pub enum Client2Server {
 pub enum Client2ServerProject {
     JoinProject,
     LeaveProject,
+    JoinProjectDoc,
+    LeaveProjectDoc,
     CreateFile {
         file_name: String,
+        /// The folder to create the file in, if not the project directory.
+        #[serde(default)]
+        folder: Option<PathBuf>,
+    },
+    CreateFolder {
+        path: PathBuf,
+    },
+    MoveFile {
+        id: FileId,
+        #[serde(default)]
+        folder: Option<PathBuf>,
     },
     Reorder {
         id: FileId,
         new_index: usize,
     },
+    UpdateMetadata {
+        metadata: Metadata,
+    },
+    Export {
+        format: ExportFormat,
+        #[serde(default)]
+        options: ExportOptions,
+    },
+    CancelExport {
+        job: JobId,
+    },
+    EnableHistory,
+    Commit {
+        message: String,
+    },
+    GetHistory,
+    CreateSnapshot {
+        name: String,
+    },
+    GetSnapshots,
     File {
         id: FileId,
         msg: Client2ServerProjectFile,
@@ -38,7 +84,24 @@ pub enum Client2ServerProject {
 pub enum Client2ServerProjectFile {
     JoinFileSource,
     LeaveFileSource,
-    EditFileSource { contents: String },
+    EditFileSource {
+        contents: String,
+    },
     JoinFileDoc,
     LeaveFileDoc,
+    GetRevision {
+        revision: String,
+    },
+    RestoreRevision {
+        revision: String,
+    },
+    DiffSnapshot {
+        snapshot: SnapshotId,
+    },
+    Undo,
+    Redo,
+    /// Reverts to the source at `time`, in seconds since the unix epoch.
+    RevertTo {
+        time: u64,
+    },
 }
@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Default upper bound on the size of a projects compile cache.
+pub const DEFAULT_CACHE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// A stable hash used to address cache entries.
+///
+/// Every part is length prefixed before hashing, so `["ab", "c"]` and
+/// `["a", "bc"]` produce different keys.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct CacheKey(String);
+
+impl CacheKey {
+    pub fn new(parts: &[&[u8]]) -> CacheKey {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.input(&(part.len() as u64).to_le_bytes());
+            hasher.input(part);
+        }
+        CacheKey(format!("{:x}", hasher.result()))
+    }
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    /// A shorter version of the key, suitable for file names.
+    pub fn short(&self) -> &str {
+        &self.0[..16]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CacheEntry {
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CacheIndex {
+    clock: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+/// An on-disk cache of compile outputs, stored in the project directory so it
+/// survives restarts. Entries are evicted least recently used first once the
+/// total size exceeds `max_size`.
+pub struct CompileCache {
+    dir: PathBuf,
+    max_size: u64,
+    index: CacheIndex,
+    /// Whether the index changed since it was last written. Hits only move
+    /// entries up in the eviction order, so they are written along with the
+    /// next insert rather than on every hit.
+    dirty: bool,
+}
+
+impl CompileCache {
+    pub fn open(dir: PathBuf, max_size: u64) -> io::Result<CompileCache> {
+        fs::create_dir_all(&dir)?;
+
+        let index = fs::read_to_string(dir.join("index.json"))
+            .ok()
+            .and_then(|index| serde_json::from_str(&index).ok())
+            .unwrap_or_default();
+
+        let mut cache = CompileCache {
+            dir,
+            max_size,
+            index,
+            dirty: false,
+        };
+        // Entries whose data went missing are useless, so forget about them
+        let dir = cache.dir.clone();
+        cache.index.entries.retain(|key, _| dir.join(key).is_file());
+
+        Ok(cache)
+    }
+    fn entry_path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(key.as_str())
+    }
+    fn touch(&mut self, key: &CacheKey, size: u64) {
+        self.index.clock += 1;
+        let last_used = self.index.clock;
+        self.index
+            .entries
+            .insert(key.as_str().to_string(), CacheEntry { size, last_used });
+        self.dirty = true;
+    }
+    fn save_index(&mut self) -> io::Result<()> {
+        fs::write(
+            self.dir.join("index.json"),
+            serde_json::to_string(&self.index).unwrap(),
+        )?;
+        self.dirty = false;
+        Ok(())
+    }
+    pub fn get(&mut self, key: &CacheKey) -> Option<Vec<u8>> {
+        if !self.index.entries.contains_key(key.as_str()) {
+            return None;
+        }
+        match fs::read(self.entry_path(key)) {
+            Ok(data) => {
+                self.touch(key, data.len() as u64);
+                Some(data)
+            }
+            Err(_) => {
+                self.index.entries.remove(key.as_str());
+                self.dirty = true;
+                None
+            }
+        }
+    }
+    pub fn get_string(&mut self, key: &CacheKey) -> Option<String> {
+        self.get(key)
+            .map(|data| String::from_utf8_lossy(&data).to_string())
+    }
+    /// Copies the cached data to `dest`, returning whether the key was present.
+    pub fn get_to_file(&mut self, key: &CacheKey, dest: &Path) -> io::Result<bool> {
+        match self.get(key) {
+            Some(data) => {
+                if !dest.is_file() {
+                    fs::write(dest, data)?;
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+    pub fn insert(&mut self, key: &CacheKey, data: &[u8]) -> io::Result<()> {
+        fs::write(self.entry_path(key), data)?;
+        self.touch(key, data.len() as u64);
+        self.evict()?;
+        self.save_index()
+    }
+    fn evict(&mut self) -> io::Result<()> {
+        let mut total: u64 = self.index.entries.values().map(|e| e.size).sum();
+        if total <= self.max_size {
+            return Ok(());
+        }
+
+        let mut by_age: Vec<(String, CacheEntry)> = self
+            .index
+            .entries
+            .iter()
+            .map(|(k, e)| (k.clone(), e.clone()))
+            .collect();
+        by_age.sort_by_key(|(_, e)| e.last_used);
+
+        for (key, entry) in by_age {
+            if total <= self.max_size {
+                break;
+            }
+            let _ = fs::remove_file(self.dir.join(&key));
+            self.index.entries.remove(&key);
+            total -= entry.size;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for CompileCache {
+    /// Keeps the order of recent hits for the next time the cache is opened.
+    fn drop(&mut self) {
+        if self.dirty {
+            let _ = self.save_index();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    fn key(s: &str) -> CacheKey {
+        CacheKey::new(&[s.as_bytes()])
+    }
+
+    fn index(dir: &Path) -> String {
+        fs::read_to_string(dir.join("index.json")).unwrap()
+    }
+
+    #[test]
+    fn keys_are_length_prefixed() {
+        assert_eq!(CacheKey::new(&[b"ab", b"c"]), CacheKey::new(&[b"ab", b"c"]));
+        assert_ne!(CacheKey::new(&[b"ab", b"c"]), CacheKey::new(&[b"a", b"bc"]));
+        assert_ne!(CacheKey::new(&[b"abc"]), CacheKey::new(&[b"ab", b"c"]));
+    }
+
+    #[test]
+    fn least_recently_used_are_evicted() {
+        let dir = TempDir::new("cache").unwrap();
+        let mut cache = CompileCache::open(dir.path().to_path_buf(), 10).unwrap();
+        cache.insert(&key("a"), b"aaaa").unwrap();
+        cache.insert(&key("b"), b"bbbb").unwrap();
+        assert_eq!(cache.get(&key("a")), Some(b"aaaa".to_vec()));
+        cache.insert(&key("c"), b"cccc").unwrap();
+
+        assert_eq!(cache.get(&key("b")), None);
+        assert!(!dir.path().join(key("b").as_str()).exists());
+        assert_eq!(cache.get(&key("a")), Some(b"aaaa".to_vec()));
+        assert_eq!(cache.get(&key("c")), Some(b"cccc".to_vec()));
+    }
+
+    #[test]
+    fn hits_are_saved_lazily() {
+        let dir = TempDir::new("cache").unwrap();
+        let mut cache = CompileCache::open(dir.path().to_path_buf(), 10).unwrap();
+        cache.insert(&key("a"), b"aaaa").unwrap();
+        cache.insert(&key("b"), b"bbbb").unwrap();
+        let saved = index(dir.path());
+        cache.get(&key("a")).unwrap();
+        assert_eq!(index(dir.path()), saved);
+
+        // The hit is kept once the cache is closed, so "b" is evicted first
+        drop(cache);
+        let mut cache = CompileCache::open(dir.path().to_path_buf(), 10).unwrap();
+        cache.insert(&key("c"), b"cccc").unwrap();
+        assert_eq!(cache.get(&key("b")), None);
+        assert_eq!(cache.get(&key("a")), Some(b"aaaa".to_vec()));
+    }
+}
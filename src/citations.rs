@@ -0,0 +1,243 @@
+use pandoc_types::definition::{Block, Inline};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::diagnostic::Diagnostic;
+
+/// Where citations are looked up and how they are formatted. Paths are
+/// relative to the project directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct CitationSettings {
+    /// BibTeX (`.bib`) or CSL JSON (`.json`) files.
+    pub bibliography: Vec<PathBuf>,
+    /// Citation style, defaulting to pandoc's Chicago author-date.
+    pub csl: Option<PathBuf>,
+}
+
+impl CitationSettings {
+    pub fn enabled(&self) -> bool {
+        !self.bibliography.is_empty()
+    }
+    /// Arguments making pandoc process citations.
+    pub fn pandoc_args(&self, project_dir: &Path) -> Vec<OsString> {
+        if !self.enabled() {
+            return vec![];
+        }
+        let mut args: Vec<OsString> = vec!["--citeproc".into()];
+        for path in &self.bibliography {
+            let mut arg = OsString::from("--bibliography=");
+            arg.push(project_dir.join(path));
+            args.push(arg);
+        }
+        if let Some(csl) = &self.csl {
+            let mut arg = OsString::from("--csl=");
+            arg.push(project_dir.join(csl));
+            args.push(arg);
+        }
+        args
+    }
+    /// The keys of all entries in the bibliography files.
+    pub fn known_keys(&self, project_dir: &Path) -> io::Result<HashSet<String>> {
+        let mut keys = HashSet::new();
+        for path in &self.bibliography {
+            let contents = fs::read_to_string(project_dir.join(path))?;
+            if path.extension().map_or(false, |ext| ext == "json") {
+                keys.extend(csl_json_keys(&contents)?);
+            } else {
+                keys.extend(bibtex_keys(&contents));
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// Entry keys of a BibTeX file, as in `@article{key, ...}`.
+fn bibtex_keys(src: &str) -> Vec<String> {
+    src.split('@')
+        .skip(1)
+        .filter_map(|entry| {
+            let open = entry.find(|c| c == '{' || c == '(')?;
+            let kind = entry[..open].trim().to_lowercase();
+            // These are not references
+            if kind == "comment" || kind == "preamble" || kind == "string" {
+                return None;
+            }
+            let key = entry[open + 1..].split(',').next()?.trim();
+            if key.is_empty() || key.contains(char::is_whitespace) {
+                None
+            } else {
+                Some(key.to_string())
+            }
+        })
+        .collect()
+}
+
+/// Ids of the references in a CSL JSON file.
+fn csl_json_keys(src: &str) -> io::Result<Vec<String>> {
+    #[derive(Deserialize)]
+    struct Reference {
+        id: serde_json::Value,
+    }
+    let refs: Vec<Reference> =
+        serde_json::from_str(src).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(refs
+        .into_iter()
+        .map(|r| match r.id {
+            serde_json::Value::String(id) => id,
+            id => id.to_string(),
+        })
+        .collect())
+}
+
+/// The keys cited in a doc, in the order they are first cited. Cross-references
+/// are not citations, so they are left out. Citations are looked for in lists,
+/// quotes, divs and notes too, which the transforms walking a doc leave alone.
+pub fn cited_keys(doc: &[Block]) -> Vec<String> {
+    let mut keys = vec![];
+    for block in doc {
+        block_keys(block, &mut keys);
+    }
+    keys
+}
+
+fn block_keys(block: &Block, keys: &mut Vec<String>) {
+    match block {
+        Block::Plain(inlines) | Block::Para(inlines) | Block::Header(_, _, inlines) => {
+            inline_keys(inlines, keys)
+        }
+        Block::BlockQuote(blocks) | Block::Div(_, blocks) => {
+            blocks.iter().for_each(|block| block_keys(block, keys))
+        }
+        Block::OrderedList(_, items) | Block::BulletList(items) => items
+            .iter()
+            .flatten()
+            .for_each(|block| block_keys(block, keys)),
+        _ => {}
+    }
+}
+
+fn inline_keys(inlines: &[Inline], keys: &mut Vec<String>) {
+    for inline in inlines {
+        match inline {
+            Inline::Cite(citations, _) => {
+                for citation in citations {
+                    let key = &citation.citation_id;
+                    if !crate::crossref::is_reference(key) && !keys.contains(key) {
+                        keys.push(key.clone());
+                    }
+                }
+            }
+            Inline::Emph(inlines)
+            | Inline::Strong(inlines)
+            | Inline::Strikeout(inlines)
+            | Inline::Superscript(inlines)
+            | Inline::Subscript(inlines)
+            | Inline::SmallCaps(inlines)
+            | Inline::Quoted(_, inlines)
+            | Inline::Span(_, inlines)
+            | Inline::Link(_, inlines, _) => inline_keys(inlines, keys),
+            Inline::Note(blocks) => blocks.iter().for_each(|block| block_keys(block, keys)),
+            _ => {}
+        }
+    }
+}
+
+/// Warnings for citations in `doc` which are not in the bibliography.
+pub fn unresolved(
+    settings: &CitationSettings,
+    project_dir: &Path,
+    src: &str,
+    doc: &[Block],
+) -> Vec<Diagnostic> {
+    let cited = cited_keys(doc);
+    if cited.is_empty() {
+        return vec![];
+    }
+    let known = match settings.known_keys(project_dir) {
+        Ok(known) => known,
+        Err(e) => {
+            return vec![Diagnostic::warning_at(
+                src,
+                &format!("@{}", cited[0]),
+                format!("failed to read bibliography: {}", e),
+            )]
+        }
+    };
+    cited
+        .into_iter()
+        .filter(|key| !known.contains(key))
+        .map(|key| {
+            Diagnostic::warning_at(
+                src,
+                &format!("@{}", key),
+                format!("citation {:?} is not in the bibliography", key),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pandoc_types::definition::{Citation, CitationMode};
+
+    fn cite(keys: &[&str]) -> Inline {
+        let citations = keys
+            .iter()
+            .map(|key| Citation {
+                citation_id: key.to_string(),
+                citation_prefix: vec![],
+                citation_suffix: vec![],
+                citation_mode: CitationMode::NormalCitation,
+                citation_note_num: 0,
+                citation_hash: 0,
+            })
+            .collect();
+        Inline::Cite(citations, vec![])
+    }
+
+    #[test]
+    fn bibtex() {
+        let src = r#"
+@comment{not an entry}
+@string{jan = "January"}
+@Article{knuth84,
+  author = {Donald Knuth},
+  title = {Literate Programming},
+}
+@book( lamport94 , title = "LaTeX")
+@misc{, title = {no key}}
+"#;
+        assert_eq!(bibtex_keys(src), ["knuth84", "lamport94"]);
+    }
+
+    #[test]
+    fn csl_json() {
+        let src = r#"[{"id": "knuth84", "type": "article"}, {"id": 1994}]"#;
+        assert_eq!(csl_json_keys(src).unwrap(), ["knuth84", "1994"]);
+        assert!(csl_json_keys("{").is_err());
+    }
+
+    #[test]
+    fn cited_keys_in_order_without_references() {
+        let doc = vec![
+            Block::Para(vec![
+                cite(&["b", "fig:plot"]),
+                Inline::Space,
+                cite(&["a", "b"]),
+            ]),
+            Block::BulletList(vec![vec![Block::Plain(vec![Inline::Note(vec![
+                Block::Para(vec![Inline::Emph(vec![cite(&["c"])])]),
+            ])])]]),
+        ];
+        assert_eq!(cited_keys(&doc), ["b", "a", "c"]);
+    }
+}
@@ -5,13 +5,20 @@ use actix_web_actors::ws;
 use serde::{Deserialize, Serialize};
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 
+use crate::export::{ExportFormat, ExportOptions, JobId};
 use crate::hub::{self, GetProject, Hub};
 use crate::project::file::{File, FileId};
+use crate::project::metadata::Metadata;
 use crate::project::{Project, ProjectId};
 use crate::project_actor::{
-    EditFile, JoinFile, JoinProject, LeaveFile, LeaveProject, ListenKind, ProjectActor, ReorderFile,
+    CancelExport, Commit, CreateFile, CreateFolder, CreateSnapshot, DiffSnapshot, EditFile,
+    EnableHistory, GetHistory, GetRevision, GetSnapshots, JoinFile, JoinProject, JoinProjectDoc,
+    LeaveFile, LeaveProject, LeaveProjectDoc, ListenKind, MoveFile, ProjectActor, RedoEdit,
+    ReorderFile, RestoreRevision, RevertFile, StartExport, UndoEdit, UpdateMetadata,
 };
+use crate::snapshot::SnapshotId;
 
 use crate::c2s::*;
 use crate::s2c::*;
@@ -126,6 +133,30 @@ impl Client {
                 act.projects.remove(&project_id);
             })
     }
+    pub fn join_project_doc(
+        &mut self,
+        project_id: ProjectId,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        let client_id = self.id;
+        let my_adder = ctx.address();
+        self.get_project(project_id, ctx).map(move |project, _, _| {
+            project.do_send(JoinProjectDoc {
+                addr: my_adder,
+                client_id,
+            });
+        })
+    }
+    pub fn leave_project_doc(
+        &mut self,
+        project_id: ProjectId,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        let client_id = self.id;
+        self.get_project(project_id, ctx).map(move |project, _, _| {
+            project.do_send(LeaveProjectDoc { client_id });
+        })
+    }
     pub fn join_file(
         &mut self,
         project_id: ProjectId,
@@ -197,6 +228,240 @@ impl Client {
             });
         })
     }
+    pub fn create_file(
+        &mut self,
+        project_id: ProjectId,
+        name: String,
+        folder: Option<PathBuf>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(CreateFile {
+                    name,
+                    folder,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn create_folder(
+        &mut self,
+        project_id: ProjectId,
+        path: PathBuf,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(CreateFolder {
+                    path,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn move_file(
+        &mut self,
+        project_id: ProjectId,
+        file_id: FileId,
+        folder: Option<PathBuf>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(MoveFile {
+                    id: file_id,
+                    folder,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn update_metadata(
+        &mut self,
+        project_id: ProjectId,
+        metadata: Metadata,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(UpdateMetadata {
+                    metadata,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn export(
+        &mut self,
+        project_id: ProjectId,
+        format: ExportFormat,
+        options: ExportOptions,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, act, ctx| {
+                project.do_send(StartExport {
+                    format,
+                    options,
+                    client_id: act.id,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn cancel_export(
+        &mut self,
+        project_id: ProjectId,
+        job: JobId,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, act, _| {
+                project.do_send(CancelExport {
+                    job,
+                    client_id: act.id,
+                });
+            })
+    }
+    pub fn enable_history(
+        &mut self,
+        project_id: ProjectId,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(EnableHistory {
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn commit(
+        &mut self,
+        project_id: ProjectId,
+        message: String,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(Commit {
+                    message,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn get_history(
+        &mut self,
+        project_id: ProjectId,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(GetHistory {
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn get_revision(
+        &mut self,
+        project_id: ProjectId,
+        file_id: FileId,
+        revision: String,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(GetRevision {
+                    file_id,
+                    revision,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn restore_revision(
+        &mut self,
+        project_id: ProjectId,
+        file_id: FileId,
+        revision: String,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(RestoreRevision {
+                    file_id,
+                    revision,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn create_snapshot(
+        &mut self,
+        project_id: ProjectId,
+        name: String,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(CreateSnapshot {
+                    name,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn get_snapshots(
+        &mut self,
+        project_id: ProjectId,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(GetSnapshots {
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn diff_snapshot(
+        &mut self,
+        project_id: ProjectId,
+        file_id: FileId,
+        snapshot: SnapshotId,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx)
+            .map(move |project, _, ctx| {
+                project.do_send(DiffSnapshot {
+                    file_id,
+                    snapshot,
+                    addr: ctx.address(),
+                });
+            })
+    }
+    pub fn undo(
+        &mut self,
+        project_id: ProjectId,
+        file_id: FileId,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx).map(move |project, _, _| {
+            project.do_send(UndoEdit { file_id });
+        })
+    }
+    pub fn redo(
+        &mut self,
+        project_id: ProjectId,
+        file_id: FileId,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx).map(move |project, _, _| {
+            project.do_send(RedoEdit { file_id });
+        })
+    }
+    pub fn revert_file(
+        &mut self,
+        project_id: ProjectId,
+        file_id: FileId,
+        time: u64,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> impl fut::ActorFuture<Actor = Self, Error = (), Item = ()> {
+        self.get_project(project_id, ctx).map(move |project, _, _| {
+            project.do_send(RevertFile { file_id, time });
+        })
+    }
 }
 
 impl Actor for Client {
@@ -223,8 +488,37 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for Client {
                 let msg: Client2Server = serde_json::from_str(&contents).unwrap();
                 println!("Got: {:?}", msg);
                 match msg {
-                    Client2Server::CreateProject { .. } => {
-                        println!("unhandled create event");
+                    Client2Server::CreateProject {
+                        project_name,
+                        template,
+                    } => {
+                        let f = self
+                            .hub
+                            .send(hub::CreateProject {
+                                name: project_name,
+                                template,
+                            })
+                            .into_actor(self)
+                            .map(|res, _, ctx| {
+                                if let Err(error) = res {
+                                    Client::send(Server2Client::Error { error }, ctx);
+                                }
+                            })
+                            .map_err(|e, _, _| println!("failed to create project: {}", e));
+                        ctx.wait(f);
+                    }
+                    Client2Server::ImportDirectory { path, name } => {
+                        let f = self
+                            .hub
+                            .send(hub::ImportDirectory { path, name })
+                            .into_actor(self)
+                            .map(|res, _, ctx| {
+                                if let Err(error) = res {
+                                    Client::send(Server2Client::Error { error }, ctx);
+                                }
+                            })
+                            .map_err(|e, _, _| println!("failed to import: {}", e));
+                        ctx.wait(f);
                     }
                     Client2Server::Project {
                         id: project_id,
@@ -238,6 +532,14 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for Client {
                             let f = self.leave_project(project_id, ctx);
                             ctx.wait(f);
                         }
+                        Client2ServerProject::JoinProjectDoc => {
+                            let f = self.join_project_doc(project_id, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::LeaveProjectDoc => {
+                            let f = self.leave_project_doc(project_id, ctx);
+                            ctx.wait(f);
+                        }
                         Client2ServerProject::Reorder {
                             id: file_id,
                             new_index,
@@ -245,6 +547,53 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for Client {
                             let f = self.reorder_file(project_id, file_id, new_index, ctx);
                             ctx.wait(f);
                         }
+                        Client2ServerProject::CreateFile { file_name, folder } => {
+                            let f = self.create_file(project_id, file_name, folder, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::CreateFolder { path } => {
+                            let f = self.create_folder(project_id, path, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::MoveFile {
+                            id: file_id,
+                            folder,
+                        } => {
+                            let f = self.move_file(project_id, file_id, folder, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::UpdateMetadata { metadata } => {
+                            let f = self.update_metadata(project_id, metadata, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::Export { format, options } => {
+                            let f = self.export(project_id, format, options, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::CancelExport { job } => {
+                            let f = self.cancel_export(project_id, job, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::EnableHistory => {
+                            let f = self.enable_history(project_id, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::Commit { message } => {
+                            let f = self.commit(project_id, message, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::GetHistory => {
+                            let f = self.get_history(project_id, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::CreateSnapshot { name } => {
+                            let f = self.create_snapshot(project_id, name, ctx);
+                            ctx.wait(f);
+                        }
+                        Client2ServerProject::GetSnapshots => {
+                            let f = self.get_snapshots(project_id, ctx);
+                            ctx.wait(f);
+                        }
                         Client2ServerProject::File { id: file_id, msg } => match msg {
                             Client2ServerProjectFile::JoinFileSource => {
                                 let f = self.join_file(project_id, file_id, ListenKind::Src, ctx);
@@ -266,10 +615,31 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for Client {
                                 let f = self.edit_file(project_id, file_id, contents, ctx);
                                 ctx.wait(f);
                             }
+                            Client2ServerProjectFile::GetRevision { revision } => {
+                                let f = self.get_revision(project_id, file_id, revision, ctx);
+                                ctx.wait(f);
+                            }
+                            Client2ServerProjectFile::RestoreRevision { revision } => {
+                                let f = self.restore_revision(project_id, file_id, revision, ctx);
+                                ctx.wait(f);
+                            }
+                            Client2ServerProjectFile::DiffSnapshot { snapshot } => {
+                                let f = self.diff_snapshot(project_id, file_id, snapshot, ctx);
+                                ctx.wait(f);
+                            }
+                            Client2ServerProjectFile::Undo => {
+                                let f = self.undo(project_id, file_id, ctx);
+                                ctx.wait(f);
+                            }
+                            Client2ServerProjectFile::Redo => {
+                                let f = self.redo(project_id, file_id, ctx);
+                                ctx.wait(f);
+                            }
+                            Client2ServerProjectFile::RevertTo { time } => {
+                                let f = self.revert_file(project_id, file_id, time, ctx);
+                                ctx.wait(f);
+                            }
                         },
-                        _ => {
-                            println!("unhandled project event {:?}", msg);
-                        }
                     },
                 }
                 // self.hub.do_send(Msg::Message { contents });
@@ -0,0 +1,523 @@
+use pandoc_types::definition::{Attr, Block, Citation, Inline, MathType, Meta, Pandoc, Target};
+
+use std::collections::HashMap;
+
+use crate::project::file::Doc;
+use crate::walk_pandoc::{walk_pandoc, Walk};
+
+/// What a label such as `fig:plot` refers to, given by its prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefKind {
+    Figure,
+    Table,
+    Equation,
+    Section,
+}
+
+impl RefKind {
+    pub fn from_label(label: &str) -> Option<RefKind> {
+        match label.split(':').next() {
+            Some("fig") if label.len() > 4 => Some(RefKind::Figure),
+            Some("tbl") if label.len() > 4 => Some(RefKind::Table),
+            Some("eq") if label.len() > 3 => Some(RefKind::Equation),
+            Some("sec") if label.len() > 4 => Some(RefKind::Section),
+            _ => None,
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            RefKind::Figure => "Figure",
+            RefKind::Table => "Table",
+            RefKind::Equation => "Equation",
+            RefKind::Section => "Section",
+        }
+    }
+}
+
+/// Whether a citation key is really a cross-reference.
+pub fn is_reference(key: &str) -> bool {
+    RefKind::from_label(key).is_some()
+}
+
+/// Numbers labelled figures, tables, equations and sections.
+#[derive(Default)]
+struct Numbering {
+    labels: HashMap<String, String>,
+    figures: usize,
+    tables: usize,
+    equations: usize,
+    sections: Vec<usize>,
+    /// The level of the highest headers, which are numbered as top level
+    /// sections, so a document starting with `##` is not numbered "0.1".
+    top_level: i32,
+}
+
+impl Numbering {
+    /// Prefixes a caption with its number, as in "Figure 2: ...".
+    fn caption(kind: RefKind, number: usize, caption: Vec<Inline>) -> Vec<Inline> {
+        let mut numbered = vec![
+            Inline::Str(kind.name().to_string()),
+            Inline::Space,
+            Inline::Str(format!("{}:", number)),
+        ];
+        if !caption.is_empty() {
+            numbered.push(Inline::Space);
+            numbered.extend(caption);
+        }
+        numbered
+    }
+    /// Numbers display math followed by an `{#eq:label}` attribute.
+    fn equations(&mut self, inlines: Vec<Inline>) -> Vec<Inline> {
+        let mut out: Vec<Inline> = Vec::with_capacity(inlines.len());
+        for inline in inlines {
+            let label = match &inline {
+                Inline::Str(s) => attribute_label(s, RefKind::Equation),
+                _ => None,
+            };
+            let label = match label {
+                Some(label) => label,
+                None => {
+                    out.push(inline);
+                    continue;
+                }
+            };
+            // The attribute must directly follow the math, maybe after a space
+            let space = match out.last() {
+                Some(Inline::Space) => 1,
+                _ => 0,
+            };
+            let is_math = match out.iter().rev().nth(space) {
+                Some(Inline::Math(MathType::DisplayMath, _)) => true,
+                _ => false,
+            };
+            if !is_math {
+                out.push(inline);
+                continue;
+            }
+            for _ in 0..space {
+                out.pop();
+            }
+            let tex = match out.pop() {
+                Some(Inline::Math(_, tex)) => tex,
+                _ => unreachable!(),
+            };
+
+            self.equations += 1;
+            self.labels
+                .insert(label.clone(), self.equations.to_string());
+            out.push(Inline::Span(
+                Attr(label, vec![], vec![]),
+                vec![Inline::Math(
+                    MathType::DisplayMath,
+                    format!("{} \\tag{{{}}}", tex, self.equations),
+                )],
+            ));
+        }
+        out
+    }
+}
+
+impl Walk for Numbering {
+    fn block(&mut self, block: Block) -> Vec<Block> {
+        match block {
+            Block::Header(level, attr, inlines) => {
+                let depth = (level - self.top_level + 1).max(1) as usize;
+                self.sections.resize(depth, 0);
+                self.sections[depth - 1] += 1;
+                if RefKind::from_label(&attr.0) == Some(RefKind::Section) {
+                    let number: Vec<String> = self.sections.iter().map(|n| n.to_string()).collect();
+                    self.labels.insert(attr.0.clone(), number.join("."));
+                }
+                vec![Block::Header(level, attr, inlines)]
+            }
+            Block::Table(mut caption, aligns, widths, head, rows) => {
+                let label = match caption.last() {
+                    Some(Inline::Str(s)) => attribute_label(s, RefKind::Table),
+                    _ => None,
+                };
+                let label = match label {
+                    Some(label) => label,
+                    None => return vec![Block::Table(caption, aligns, widths, head, rows)],
+                };
+                caption.pop();
+                if let Some(Inline::Space) = caption.last() {
+                    caption.pop();
+                }
+
+                self.tables += 1;
+                self.labels.insert(label.clone(), self.tables.to_string());
+                let caption = Numbering::caption(RefKind::Table, self.tables, caption);
+                // Wrapped so the table can be linked to
+                vec![Block::Div(
+                    Attr(label, vec![], vec![]),
+                    vec![Block::Table(caption, aligns, widths, head, rows)],
+                )]
+            }
+            Block::Para(inlines) => vec![Block::Para(self.equations(inlines))],
+            Block::Plain(inlines) => vec![Block::Plain(self.equations(inlines))],
+            block => vec![block],
+        }
+    }
+    fn inline(&mut self, inline: Inline) -> Vec<Inline> {
+        match inline {
+            Inline::Image(attr, caption, target)
+                if RefKind::from_label(&attr.0) == Some(RefKind::Figure) =>
+            {
+                self.figures += 1;
+                self.labels.insert(attr.0.clone(), self.figures.to_string());
+                let caption = Numbering::caption(RefKind::Figure, self.figures, caption);
+                vec![Inline::Image(attr, caption, target)]
+            }
+            inline => vec![inline],
+        }
+    }
+}
+
+/// The label of an attribute like `{#tbl:results}`, if it is of the given
+/// kind.
+fn attribute_label(s: &str, kind: RefKind) -> Option<String> {
+    if !s.starts_with("{#") || !s.ends_with('}') {
+        return None;
+    }
+    let label = &s[2..s.len() - 1];
+    if RefKind::from_label(label) == Some(kind) {
+        Some(label.to_string())
+    } else {
+        None
+    }
+}
+
+/// Replaces references with links to what they refer to.
+struct Resolve<'a> {
+    labels: &'a HashMap<String, String>,
+    unresolved: Vec<String>,
+}
+
+impl<'a> Resolve<'a> {
+    /// A link to what a reference refers to, if it is defined.
+    fn link(&self, label: &str) -> Option<Inline> {
+        let kind = RefKind::from_label(label)?;
+        let number = self.labels.get(label)?;
+        let number = if kind == RefKind::Equation {
+            format!("({})", number)
+        } else {
+            number.clone()
+        };
+        Some(Inline::Link(
+            Attr::null(),
+            vec![
+                Inline::Str(kind.name().to_string()),
+                Inline::Space,
+                Inline::Str(number),
+            ],
+            Target(format!("#{}", label), String::new()),
+        ))
+    }
+}
+
+impl<'a> Walk for Resolve<'a> {
+    fn inline(&mut self, inline: Inline) -> Vec<Inline> {
+        let (citations, text) = match inline {
+            Inline::Cite(citations, text) => (citations, text),
+            inline => return vec![inline],
+        };
+        for citation in &citations {
+            let label = &citation.citation_id;
+            if is_reference(label) && !self.labels.contains_key(label) {
+                self.unresolved.push(label.clone());
+            }
+        }
+        // Unknown references are reported, and stay as they were written
+        if citations
+            .iter()
+            .all(|c| self.link(&c.citation_id).is_none())
+        {
+            return vec![Inline::Cite(citations, text)];
+        }
+
+        // References in a group become links, and the citations between
+        // them are kept for citeproc
+        let mut out = vec![];
+        let mut rest = vec![];
+        for citation in citations {
+            match self.link(&citation.citation_id) {
+                Some(link) => {
+                    push_part(&mut out, cite(std::mem::replace(&mut rest, vec![])));
+                    push_part(&mut out, Some(link));
+                }
+                None => rest.push(citation),
+            }
+        }
+        push_part(&mut out, cite(rest));
+        out
+    }
+}
+
+/// Adds a part of a resolved citation group, separated from the one before.
+fn push_part(out: &mut Vec<Inline>, part: Option<Inline>) {
+    if let Some(part) = part {
+        if !out.is_empty() {
+            out.push(Inline::Str(",".to_string()));
+            out.push(Inline::Space);
+        }
+        out.push(part);
+    }
+}
+
+/// A citation of some of the keys of a group, written as pandoc would show
+/// it unprocessed.
+fn cite(citations: Vec<Citation>) -> Option<Inline> {
+    if citations.is_empty() {
+        return None;
+    }
+    let keys: Vec<String> = citations
+        .iter()
+        .map(|c| format!("@{}", c.citation_id))
+        .collect();
+    let text = format!("[{}]", keys.join("; "));
+    let mut inlines = vec![];
+    for word in text.split(' ') {
+        if !inlines.is_empty() {
+            inlines.push(Inline::Space);
+        }
+        inlines.push(Inline::Str(word.to_string()));
+    }
+    Some(Inline::Cite(citations, inlines))
+}
+
+/// A doc after cross-references were resolved.
+pub struct Linked {
+    pub doc: Doc,
+    /// Labels which were referenced, but never defined.
+    pub unresolved: Vec<String>,
+}
+
+/// Numbers the labelled items of all docs, which are parts of the same
+/// document in order, and resolves the references between them.
+pub fn link(docs: Vec<Doc>) -> Vec<Linked> {
+    let top_level = docs
+        .iter()
+        .flatten()
+        .filter_map(|block| match block {
+            Block::Header(level, _, _) => Some(*level),
+            _ => None,
+        })
+        .min()
+        .unwrap_or(1);
+    let mut numbering = Numbering {
+        top_level,
+        ..Numbering::default()
+    };
+    let numbered: Vec<Doc> = docs
+        .into_iter()
+        .map(|doc| walk_pandoc(&mut numbering, Pandoc(Meta(HashMap::new()), doc)).1)
+        .collect();
+
+    numbered
+        .into_iter()
+        .map(|doc| {
+            let mut resolve = Resolve {
+                labels: &numbering.labels,
+                unresolved: vec![],
+            };
+            let doc = walk_pandoc(&mut resolve, Pandoc(Meta(HashMap::new()), doc)).1;
+            Linked {
+                doc,
+                unresolved: resolve.unresolved,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pandoc_types::definition::CitationMode;
+
+    fn str(s: &str) -> Inline {
+        Inline::Str(s.to_string())
+    }
+
+    fn citation(key: &str) -> Citation {
+        Citation {
+            citation_id: key.to_string(),
+            citation_prefix: vec![],
+            citation_suffix: vec![],
+            citation_mode: CitationMode::NormalCitation,
+            citation_note_num: 0,
+            citation_hash: 0,
+        }
+    }
+
+    fn cite(keys: &[&str]) -> Inline {
+        let text = vec![str(&format!("[@{}]", keys.join("; @")))];
+        Inline::Cite(keys.iter().map(|key| citation(key)).collect(), text)
+    }
+
+    fn header(level: i32, id: &str) -> Block {
+        Block::Header(level, Attr(id.to_string(), vec![], vec![]), vec![str(id)])
+    }
+
+    fn reference(kind: &str, number: &str, label: &str) -> Inline {
+        Inline::Link(
+            Attr::null(),
+            vec![str(kind), Inline::Space, str(number)],
+            Target(format!("#{}", label), String::new()),
+        )
+    }
+
+    /// Resolves each reference on its own, in a paragraph after `docs`.
+    fn resolve(docs: Vec<Doc>, labels: &[&str]) -> Vec<Inline> {
+        let mut docs = docs;
+        docs.push(vec![Block::Para(
+            labels.iter().map(|l| cite(&[l])).collect(),
+        )]);
+        match link(docs).pop().unwrap().doc.pop() {
+            Some(Block::Para(inlines)) => inlines,
+            block => panic!("{:?} is not a paragraph", block),
+        }
+    }
+
+    #[test]
+    fn sections_across_files() {
+        let docs = vec![
+            vec![header(1, "sec:a"), header(2, "sec:a1")],
+            vec![
+                header(2, "sec:a2"),
+                header(1, "sec:b"),
+                header(3, "sec:b01"),
+            ],
+        ];
+        assert_eq!(
+            resolve(docs, &["sec:a1", "sec:a2", "sec:b", "sec:b01"]),
+            vec![
+                reference("Section", "1.1", "sec:a1"),
+                reference("Section", "1.2", "sec:a2"),
+                reference("Section", "2", "sec:b"),
+                reference("Section", "2.0.1", "sec:b01"),
+            ]
+        );
+    }
+
+    #[test]
+    fn sections_start_at_the_highest_level() {
+        let docs = vec![vec![
+            header(2, "sec:a"),
+            header(3, "sec:a1"),
+            header(2, "sec:b"),
+        ]];
+        assert_eq!(
+            resolve(docs, &["sec:a", "sec:a1", "sec:b"]),
+            vec![
+                reference("Section", "1", "sec:a"),
+                reference("Section", "1.1", "sec:a1"),
+                reference("Section", "2", "sec:b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn figures_tables_and_equations() {
+        let figure = |id: &str| {
+            Block::Para(vec![Inline::Image(
+                Attr(id.to_string(), vec![], vec![]),
+                vec![str("Plot")],
+                Target("plot.png".to_string(), "fig:".to_string()),
+            )])
+        };
+        let docs = vec![
+            vec![figure("fig:a"), figure("")],
+            vec![
+                figure("fig:b"),
+                Block::Table(
+                    vec![str("Results"), Inline::Space, str("{#tbl:r}")],
+                    vec![],
+                    vec![],
+                    vec![],
+                    vec![],
+                ),
+                Block::Para(vec![
+                    Inline::Math(MathType::DisplayMath, "x".to_string()),
+                    Inline::Space,
+                    str("{#eq:x}"),
+                ]),
+            ],
+        ];
+        let linked = link(docs.clone());
+        assert_eq!(
+            linked[1].doc[0],
+            Block::Para(vec![Inline::Image(
+                Attr("fig:b".to_string(), vec![], vec![]),
+                vec![
+                    str("Figure"),
+                    Inline::Space,
+                    str("2:"),
+                    Inline::Space,
+                    str("Plot")
+                ],
+                Target("plot.png".to_string(), "fig:".to_string()),
+            )])
+        );
+        assert_eq!(
+            linked[1].doc[1],
+            Block::Div(
+                Attr("tbl:r".to_string(), vec![], vec![]),
+                vec![Block::Table(
+                    vec![
+                        str("Table"),
+                        Inline::Space,
+                        str("1:"),
+                        Inline::Space,
+                        str("Results")
+                    ],
+                    vec![],
+                    vec![],
+                    vec![],
+                    vec![],
+                )],
+            )
+        );
+        assert_eq!(
+            resolve(docs, &["fig:b", "tbl:r", "eq:x"]),
+            vec![
+                reference("Figure", "2", "fig:b"),
+                reference("Table", "1", "tbl:r"),
+                reference("Equation", "(1)", "eq:x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn mixed_citation_groups() {
+        let docs = vec![
+            vec![header(1, "sec:a")],
+            vec![Block::Para(vec![cite(&["smith", "sec:a", "jones", "doe"])])],
+        ];
+        let linked = link(docs);
+        assert_eq!(
+            linked[1].doc,
+            vec![Block::Para(vec![
+                Inline::Cite(vec![citation("smith")], vec![str("[@smith]")]),
+                str(","),
+                Inline::Space,
+                reference("Section", "1", "sec:a"),
+                str(","),
+                Inline::Space,
+                Inline::Cite(
+                    vec![citation("jones"), citation("doe")],
+                    vec![str("[@jones;"), Inline::Space, str("@doe]")],
+                ),
+            ])]
+        );
+        assert!(linked[1].unresolved.is_empty());
+    }
+
+    #[test]
+    fn unresolved_references_are_kept_and_reported() {
+        let docs = vec![vec![Block::Para(vec![cite(&["fig:missing", "smith"])])]];
+        let linked = link(docs.clone());
+        assert_eq!(linked[0].doc, docs[0]);
+        assert_eq!(linked[0].unresolved, ["fig:missing"]);
+    }
+}
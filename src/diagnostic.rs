@@ -0,0 +1,35 @@
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found while compiling a file, shown next to the source.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The line of the source the problem is on, starting from 1, when known.
+    pub line: Option<usize>,
+}
+
+impl Diagnostic {
+    /// A warning on the first line of `src` containing `needle`.
+    pub fn warning_at(src: &str, needle: &str, message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            message,
+            line: find_line(src, needle),
+        }
+    }
+}
+
+/// The line number of the first line containing `needle`.
+pub fn find_line(src: &str, needle: &str) -> Option<usize> {
+    src.lines()
+        .position(|line| line.contains(needle))
+        .map(|i| i + 1)
+}
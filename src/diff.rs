@@ -0,0 +1,350 @@
+use pandoc_types::definition::Block;
+
+use serde::Serialize;
+
+use crate::project::file::DocEdit;
+
+/// Above this many comparisons the middle of a diff is reported as a single
+/// replacement, rather than spending time finding the smallest diff.
+const MAX_DIFF_COST: usize = 1_000_000;
+
+/// A range of `old` which was replaced by a range of `new`. Either range may
+/// be empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+}
+
+/// Finds the hunks turning `old` into `new`, using the longest common
+/// subsequence of the two. Hunks are ordered and do not overlap.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    if old_mid.is_empty() && new_mid.is_empty() {
+        return vec![];
+    }
+    if old_mid.is_empty() || new_mid.is_empty() || old_mid.len() * new_mid.len() > MAX_DIFF_COST {
+        return vec![Hunk {
+            old_start: prefix,
+            old_len: old_mid.len(),
+            new_start: prefix,
+            new_len: new_mid.len(),
+        }];
+    }
+
+    // lcs[i][j] is the length of the longest common subsequence of
+    // old_mid[i..] and new_mid[j..]
+    let (n, m) = (old_mid.len(), new_mid.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut hunks = vec![];
+    let mut current: Option<Hunk> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_mid[i] == new_mid[j] {
+            hunks.extend(current.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let hunk = current.get_or_insert(Hunk {
+            old_start: prefix + i,
+            old_len: 0,
+            new_start: prefix + j,
+            new_len: 0,
+        });
+        if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            hunk.old_len += 1;
+            i += 1;
+        } else {
+            hunk.new_len += 1;
+            j += 1;
+        }
+    }
+    hunks.extend(current);
+
+    hunks
+}
+
+/// The edits turning the `old` doc into the `new` one.
+pub fn doc_edits(old: &[Block], new: &[Block]) -> Vec<DocEdit> {
+    diff(old, new)
+        .into_iter()
+        .map(|hunk| {
+            // Earlier edits have already been applied, so the position in the
+            // new doc is the right index
+            let index = hunk.new_start;
+            let blocks = new[hunk.new_start..hunk.new_start + hunk.new_len].to_vec();
+            match (hunk.old_len, hunk.new_len) {
+                (0, _) => DocEdit::Insert { index, blocks },
+                (count, 0) => DocEdit::Remove { index, count },
+                (count, _) => DocEdit::Replace {
+                    index,
+                    count,
+                    blocks,
+                },
+            }
+        })
+        .collect()
+}
+
+/// How many unchanged lines are shown around the changes in a text diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// A line of a text diff.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum DiffLine {
+    Context { text: String },
+    Removed { text: String },
+    Added { text: String },
+}
+
+/// Changed lines along with the unchanged lines around them.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DiffHunk {
+    /// Where the hunk starts in the old and the new text, counting from one.
+    pub old_line: usize,
+    pub new_line: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+/// The lines changed between two texts, as hunks like those of a unified
+/// diff. Changes close enough for their context to overlap share a hunk.
+pub fn text_diff(old: &str, new: &str) -> Vec<DiffHunk> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let mut groups: Vec<Vec<Hunk>> = vec![];
+    for hunk in diff(&old, &new) {
+        let joined = match groups.last_mut().and_then(|group| group.last()) {
+            Some(last) => hunk.old_start - (last.old_start + last.old_len) <= 2 * DIFF_CONTEXT,
+            None => false,
+        };
+        if joined {
+            groups.last_mut().unwrap().push(hunk);
+        } else {
+            groups.push(vec![hunk]);
+        }
+    }
+
+    let context = |line: &&str| DiffLine::Context {
+        text: line.to_string(),
+    };
+    groups
+        .into_iter()
+        .map(|group| {
+            let first = &group[0];
+            let before = first.old_start.min(DIFF_CONTEXT);
+            let mut pos = first.old_start - before;
+            let mut lines = vec![];
+            for hunk in &group {
+                lines.extend(old[pos..hunk.old_start].iter().map(context));
+                lines.extend(
+                    old[hunk.old_start..hunk.old_start + hunk.old_len]
+                        .iter()
+                        .map(|line| DiffLine::Removed {
+                            text: line.to_string(),
+                        }),
+                );
+                lines.extend(
+                    new[hunk.new_start..hunk.new_start + hunk.new_len]
+                        .iter()
+                        .map(|line| DiffLine::Added {
+                            text: line.to_string(),
+                        }),
+                );
+                pos = hunk.old_start + hunk.old_len;
+            }
+            let end = (pos + DIFF_CONTEXT).min(old.len());
+            lines.extend(old[pos..end].iter().map(context));
+
+            DiffHunk {
+                old_line: first.old_start - before + 1,
+                new_line: first.new_start - before + 1,
+                lines,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pandoc_types::definition::Inline;
+
+    fn hunk(old_start: usize, old_len: usize, new_start: usize, new_len: usize) -> Hunk {
+        Hunk {
+            old_start,
+            old_len,
+            new_start,
+            new_len,
+        }
+    }
+
+    fn para(text: &str) -> Block {
+        Block::Para(vec![Inline::Str(text.to_string())])
+    }
+
+    fn doc(texts: &[&str]) -> Vec<Block> {
+        texts.iter().map(|text| para(text)).collect()
+    }
+
+    fn apply(mut doc: Vec<Block>, edits: Vec<DocEdit>) -> Vec<Block> {
+        for edit in edits {
+            match edit {
+                DocEdit::Insert { index, blocks } => {
+                    doc.splice(index..index, blocks);
+                }
+                DocEdit::Remove { index, count } => {
+                    doc.drain(index..index + count);
+                }
+                DocEdit::Replace {
+                    index,
+                    count,
+                    blocks,
+                } => {
+                    doc.splice(index..index + count, blocks);
+                }
+            }
+        }
+        doc
+    }
+
+    fn lines(n: usize) -> Vec<String> {
+        (1..=n).map(|i| format!("line {}", i)).collect()
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(diff::<u8>(&[], &[]), vec![]);
+        assert_eq!(diff(&[], &[1, 2]), vec![hunk(0, 0, 0, 2)]);
+        assert_eq!(diff(&[1, 2], &[]), vec![hunk(0, 2, 0, 0)]);
+        assert_eq!(diff(&[1, 2, 3], &[1, 2, 3]), vec![]);
+    }
+
+    #[test]
+    fn insertion() {
+        assert_eq!(diff(&[1, 2, 3], &[1, 2, 4, 5, 3]), vec![hunk(2, 0, 2, 2)]);
+        assert_eq!(diff(&[1, 2], &[0, 1, 2]), vec![hunk(0, 0, 0, 1)]);
+        assert_eq!(diff(&[1, 2], &[1, 2, 3]), vec![hunk(2, 0, 2, 1)]);
+    }
+
+    #[test]
+    fn removal() {
+        assert_eq!(diff(&[1, 2, 3, 4], &[1, 4]), vec![hunk(1, 2, 1, 0)]);
+        assert_eq!(diff(&[1, 2, 3], &[2, 3]), vec![hunk(0, 1, 0, 0)]);
+    }
+
+    #[test]
+    fn several_hunks() {
+        assert_eq!(
+            diff(&[1, 2, 3, 4, 5, 6], &[1, 7, 3, 4, 6, 8]),
+            vec![hunk(1, 1, 1, 1), hunk(4, 1, 4, 0), hunk(6, 0, 5, 1)]
+        );
+    }
+
+    #[test]
+    fn costly_diff_is_a_single_hunk() {
+        // Every other element changes, but the middle is too large to diff
+        let old: Vec<usize> = (0..1010).collect();
+        let new: Vec<usize> = old
+            .iter()
+            .map(|i| if i % 2 == 0 { *i } else { i + 5000 })
+            .collect();
+        assert_eq!(diff(&old, &new), vec![hunk(1, 1009, 1, 1009)]);
+
+        let old: Vec<usize> = (0..100).collect();
+        assert!(diff(&old, &new[..100]).len() > 1);
+    }
+
+    #[test]
+    fn doc_edits_turn_old_into_new() {
+        let cases: &[(&[&str], &[&str])] = &[
+            (&[], &["a", "b"]),
+            (&["a", "b"], &[]),
+            (&["a", "b", "c"], &["a", "x", "c"]),
+            (&["a", "b", "c", "d", "e"], &["x", "b", "d", "y", "e", "z"]),
+            (&["a", "b"], &["b", "a", "b", "c"]),
+        ];
+        for (old, new) in cases {
+            let (old, new) = (doc(old), doc(new));
+            assert_eq!(apply(old.clone(), doc_edits(&old, &new)), new);
+        }
+    }
+
+    #[test]
+    fn text_diff_merges_close_changes() {
+        let old = lines(20);
+        let mut new = old.clone();
+        // Six unchanged lines between the changes, so their context touches
+        new[4] = "changed 5".to_string();
+        new[11] = "changed 12".to_string();
+        let hunks = text_diff(&old.join("\n"), &new.join("\n"));
+        assert_eq!(hunks.len(), 1);
+        assert_eq!((hunks[0].old_line, hunks[0].new_line), (2, 2));
+        assert_eq!(hunks[0].lines.len(), 3 + 2 + 6 + 2 + 3);
+
+        // Seven lines apart they are shown separately
+        new[11] = old[11].clone();
+        new[12] = "changed 13".to_string();
+        let hunks = text_diff(&old.join("\n"), &new.join("\n"));
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[1].old_line, 10);
+    }
+
+    #[test]
+    fn text_diff_context_at_start_and_end() {
+        let old = lines(10);
+        let mut new = old.clone();
+        new[0] = "first".to_string();
+        new[9] = "last".to_string();
+        let hunks = text_diff(&old.join("\n"), &new.join("\n"));
+        assert_eq!(hunks.len(), 2);
+
+        assert_eq!((hunks[0].old_line, hunks[0].new_line), (1, 1));
+        assert_eq!(
+            hunks[0].lines[..2],
+            [
+                DiffLine::Removed {
+                    text: "line 1".to_string()
+                },
+                DiffLine::Added {
+                    text: "first".to_string()
+                },
+            ]
+        );
+        assert_eq!(hunks[0].lines.len(), 2 + 3);
+
+        assert_eq!((hunks[1].old_line, hunks[1].new_line), (7, 7));
+        assert_eq!(hunks[1].lines.len(), 3 + 2);
+        assert_eq!(
+            hunks[1].lines.last(),
+            Some(&DiffLine::Added {
+                text: "last".to_string()
+            })
+        );
+    }
+}
@@ -1,59 +1,218 @@
-use pandoc_types::definition::{Attr, Block, Inline, Pandoc, Target};
+use pandoc_types::definition::{Attr, Block, Inline, Meta, Pandoc, Target};
+
+use serde::{Deserialize, Serialize};
 
 use std::collections::HashMap;
+use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::cache::{CacheKey, CompileCache};
+
+/// Everything a compile needs to know about its surroundings.
+pub struct CompileContext<'a> {
+    /// Where code blocks are run and their artifacts are placed.
+    pub run_dir: &'a Path,
+    /// The project directory, against which inputs of code blocks are resolved.
+    pub project_dir: &'a Path,
+    pub cache: &'a Mutex<CompileCache>,
+    pub backend: BackendKind,
+    /// Link reference definitions from all files of the project, so links
+    /// can use definitions from other files.
+    pub definitions: &'a str,
+}
+
+/// A project file read by a code block during compilation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input {
+    /// Path relative to the project directory.
+    pub path: PathBuf,
+    pub modified: Option<SystemTime>,
+}
 
-pub fn compile(src: &str, root: &Path) -> io::Result<pandoc_types::definition::Pandoc> {
-    let mut cmd = Command::new("pandoc")
-        .args(&["-f", "markdown", "-t", "json"])
-        .current_dir(root)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
-    write!(cmd.stdin.as_mut().expect("failed to get stdin"), "{}", &src)?;
-    let out = cmd.wait_with_output()?;
-    let compiled = String::from_utf8_lossy(&out.stdout).to_string();
+impl Input {
+    fn new(project_dir: &Path, path: PathBuf) -> Input {
+        let modified = fs::metadata(project_dir.join(&path))
+            .and_then(|m| m.modified())
+            .ok();
+        Input { path, modified }
+    }
+    /// Whether the file has been modified (or removed) since it was read.
+    pub fn changed(&self, project_dir: &Path) -> bool {
+        *self != Input::new(project_dir, self.path.clone())
+    }
+}
 
-    let parsed: pandoc_types::definition::Pandoc =
-        serde_json::from_str(&compiled).expect("failed to parse pandoc output");
+pub struct Compiled {
+    pub pandoc: Pandoc,
+    pub inputs: Vec<Input>,
+}
+
+/// Turns markdown source into a pandoc document, before any transforms run.
+pub trait Backend {
+    fn parse(&self, src: &str, run_dir: &Path) -> io::Result<Pandoc>;
+}
+
+/// Shells out to pandoc, which understands all of pandoc's markdown.
+pub struct PandocBackend;
+
+impl Backend for PandocBackend {
+    fn parse(&self, src: &str, run_dir: &Path) -> io::Result<Pandoc> {
+        let mut cmd = Command::new("pandoc")
+            .args(&["-f", "markdown", "-t", "json"])
+            .current_dir(run_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        write!(cmd.stdin.as_mut().expect("failed to get stdin"), "{}", &src)?;
+        let out = cmd.wait_with_output()?;
+        let compiled = String::from_utf8_lossy(&out.stdout).to_string();
+
+        serde_json::from_str(&compiled).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Parses in-process, which is a lot faster than pandoc for live preview, but
+/// only understands CommonMark.
+pub struct NativeBackend;
+
+impl Backend for NativeBackend {
+    fn parse(&self, src: &str, _: &Path) -> io::Result<Pandoc> {
+        Ok(Pandoc(Meta(HashMap::new()), crate::markdown::parse(src)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Pandoc,
+    Native,
+}
+
+impl Default for BackendKind {
+    fn default() -> BackendKind {
+        BackendKind::Pandoc
+    }
+}
+
+impl BackendKind {
+    pub fn backend(self) -> &'static dyn Backend {
+        match self {
+            BackendKind::Pandoc => &PandocBackend,
+            BackendKind::Native => &NativeBackend,
+        }
+    }
+}
+
+pub fn compile(src: &str, ctx: &CompileContext) -> io::Result<Compiled> {
+    let parsed = parse(src, ctx)?;
+    Ok(transform(parsed, ctx))
+}
+
+pub fn parse(src: &str, ctx: &CompileContext) -> io::Result<Pandoc> {
+    ctx.backend.backend().parse(src, ctx.run_dir)
+}
+
+/// Runs code blocks and renders diagrams in a parsed document.
+pub fn transform(parsed: Pandoc, ctx: &CompileContext) -> Compiled {
+    let root = ctx.run_dir;
+    let cache = ctx.cache;
+
+    let mut python = RunPython {
+        run_dir: root,
+        project_dir: ctx.project_dir,
+        cache,
+        inputs: vec![],
+    };
+    let parsed = crate::walk_pandoc::walk_pandoc(&mut python, parsed);
 
     let transforms: Vec<Box<dyn crate::walk_pandoc::Walk>> = vec![
-        Box::new(RunPython { run_dir: root }),
-        Box::new(Graphviz { run_dir: root }),
+        Box::new(Diagram {
+            run_dir: root,
+            cache,
+            tool: DiagramTool::Graphviz,
+        }),
+        Box::new(Diagram {
+            run_dir: root,
+            cache,
+            tool: DiagramTool::PlantUml,
+        }),
+        Box::new(Diagram {
+            run_dir: root,
+            cache,
+            tool: DiagramTool::Mermaid,
+        }),
     ];
 
     let transformed = transforms.into_iter().fold(parsed, |p, mut t| {
         crate::walk_pandoc::walk_pandoc(&mut *t, p)
     });
 
-    Ok(transformed)
-}
-
-pub fn to_pdf(pandoc: &Pandoc, root: &Path, pdf_path: &Path) -> io::Result<()> {
-    let mut cmd = Command::new("pandoc")
-        .args(&[
-            "-f",
-            "json",
-            "-o",
-            pdf_path.to_str().expect("failed to make str from path"),
-        ])
-        .current_dir(root)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
-    let src = serde_json::to_string(pandoc).unwrap();
-    write!(cmd.stdin.as_mut().expect("failed to get stdin"), "{}", &src)?;
-    let out = cmd.wait_with_output()?;
-    let compiled = String::from_utf8_lossy(&out.stdout).to_string();
-
-    Ok(())
+    Compiled {
+        pandoc: transformed,
+        inputs: python.inputs,
+    }
 }
 
 struct RunPython<'a> {
     run_dir: &'a Path,
+    project_dir: &'a Path,
+    cache: &'a Mutex<CompileCache>,
+    inputs: Vec<Input>,
+}
+
+impl<'a> RunPython<'a> {
+    /// Finds the project files a block reads. They are either declared with
+    /// an `inputs="data.csv other.csv"` attribute, or detected as string
+    /// literals in the source naming an existing file.
+    fn find_inputs(&self, attr: &Attr, src: &str) -> Vec<PathBuf> {
+        let mut inputs: Vec<PathBuf> = attr
+            .2
+            .iter()
+            .filter(|(key, _)| key == "inputs")
+            .flat_map(|(_, value)| value.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .collect();
+
+        for line in src.lines() {
+            for (i, literal) in line.split(|c: char| c == '"' || c == '\'').enumerate() {
+                // Every other segment is inside quotes
+                if i % 2 == 0 || literal.is_empty() {
+                    continue;
+                }
+                let path = PathBuf::from(literal);
+                if self.project_dir.join(&path).is_file() && !inputs.contains(&path) {
+                    inputs.push(path);
+                }
+            }
+        }
+
+        // Inputs must stay inside the project
+        inputs.retain(|path| {
+            path.is_relative()
+                && !path
+                    .components()
+                    .any(|c| c == std::path::Component::ParentDir)
+        });
+
+        inputs
+    }
+    /// Copies the inputs next to where the block is run, so relative paths in
+    /// the source resolve.
+    fn stage_inputs(&self, inputs: &[PathBuf]) -> io::Result<()> {
+        for path in inputs {
+            let dest = self.run_dir.join(path);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(self.project_dir.join(path), dest)?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a> crate::walk_pandoc::Walk for RunPython<'a> {
@@ -66,22 +225,33 @@ impl<'a> crate::walk_pandoc::Walk for RunPython<'a> {
                 }
                 let lang = &attr.1[0];
                 if lang == "python" {
-                    lazy_static::lazy_static! {
-                        static ref PYTHON_CACHE: Mutex<HashMap<u64, String>> = Mutex::new(HashMap::new());
-                    }
-
-                    let src_hash = {
-                        use std::hash::{Hash, Hasher};
-                        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-                        src.hash(&mut hasher);
-                        hasher.finish()
+                    let inputs = self.find_inputs(&attr, &src);
+                    // Missing inputs are hashed as empty, python will report them
+                    let contents: Vec<Vec<u8>> = inputs
+                        .iter()
+                        .map(|path| fs::read(self.project_dir.join(path)).unwrap_or_default())
+                        .collect();
+                    let key = {
+                        let attr = serde_json::to_string(&attr).unwrap();
+                        let mut parts: Vec<&[u8]> = vec![attr.as_bytes(), src.as_bytes()];
+                        for (path, contents) in inputs.iter().zip(&contents) {
+                            parts.push(path.to_str().unwrap_or_default().as_bytes());
+                            parts.push(contents);
+                        }
+                        CacheKey::new(&parts)
                     };
+                    self.inputs.extend(
+                        inputs
+                            .iter()
+                            .map(|path| Input::new(self.project_dir, path.clone())),
+                    );
 
-                    let mut cache = PYTHON_CACHE.lock().unwrap();
+                    let mut cache = self.cache.lock().unwrap();
 
-                    let compiled = if cache.contains_key(&src_hash) {
-                        cache.get(&src_hash).unwrap().clone()
+                    let compiled = if let Some(compiled) = cache.get_string(&key) {
+                        compiled
                     } else {
+                        let _ = self.stage_inputs(&inputs);
                         let mut cmd = Command::new("python")
                             .current_dir(self.run_dir)
                             .stdin(Stdio::piped())
@@ -92,7 +262,7 @@ impl<'a> crate::walk_pandoc::Walk for RunPython<'a> {
                             .unwrap();
                         let out = cmd.wait_with_output().unwrap();
                         let compiled = String::from_utf8_lossy(&out.stdout).to_string();
-                        cache.insert(src_hash, compiled.clone());
+                        let _ = cache.insert(&key, compiled.as_bytes());
                         compiled
                     };
 
@@ -112,11 +282,100 @@ impl<'a> crate::walk_pandoc::Walk for RunPython<'a> {
     }
 }
 
-struct Graphviz<'a> {
+#[derive(Debug, Clone, Copy)]
+enum DiagramTool {
+    Graphviz,
+    PlantUml,
+    Mermaid,
+}
+
+impl DiagramTool {
+    fn lang(self) -> &'static str {
+        match self {
+            DiagramTool::Graphviz => "graphviz",
+            DiagramTool::PlantUml => "plantuml",
+            DiagramTool::Mermaid => "mermaid",
+        }
+    }
+    fn output_prefix(self) -> &'static str {
+        match self {
+            DiagramTool::Graphviz => "graph",
+            DiagramTool::PlantUml => "plantuml",
+            DiagramTool::Mermaid => "mermaid",
+        }
+    }
+    /// Renders `src` to a png at `output_path`, returning the tool's stderr if
+    /// it exited unsuccessfully.
+    fn render(self, src: &str, run_dir: &Path, output_path: &Path) -> io::Result<()> {
+        let output_arg = output_path.to_string_lossy().to_string();
+
+        let out = match self {
+            DiagramTool::Graphviz => {
+                let mut cmd = Command::new("dot")
+                    .args(&["-Tpng", &format!("-o{}", output_arg)])
+                    .current_dir(run_dir)
+                    .stdin(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                write!(cmd.stdin.as_mut().expect("failed to get stdin"), "{}", src)?;
+                cmd.wait_with_output()?
+            }
+            DiagramTool::PlantUml => {
+                let jar = std::env::var("PLANTUML_JAR").unwrap_or_else(|_| "plantuml.jar".into());
+                let mut cmd = Command::new("java")
+                    .args(&["-jar", &jar, "-tpng", "-pipe"])
+                    .current_dir(run_dir)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                write!(cmd.stdin.as_mut().expect("failed to get stdin"), "{}", src)?;
+                let out = cmd.wait_with_output()?;
+                if out.status.success() {
+                    fs::write(output_path, &out.stdout)?;
+                }
+                out
+            }
+            DiagramTool::Mermaid => {
+                let input_path = output_path.with_extension("mmd");
+                fs::write(&input_path, src)?;
+                Command::new("mmdc")
+                    .args(&[
+                        "-i",
+                        &input_path.to_string_lossy().to_string(),
+                        "-o",
+                        &output_arg,
+                    ])
+                    .current_dir(run_dir)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::piped())
+                    .output()?
+            }
+        };
+
+        if out.status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "{} failed: {}",
+                    self.lang(),
+                    String::from_utf8_lossy(&out.stderr)
+                ),
+            ))
+        }
+    }
+}
+
+struct Diagram<'a> {
     run_dir: &'a Path,
+    cache: &'a Mutex<CompileCache>,
+    tool: DiagramTool,
 }
 
-impl<'a> crate::walk_pandoc::Walk for Graphviz<'a> {
+impl<'a> crate::walk_pandoc::Walk for Diagram<'a> {
     fn block(&mut self, block: Block) -> Vec<Block> {
         match block {
             Block::CodeBlock(attr, src) => {
@@ -125,43 +384,22 @@ impl<'a> crate::walk_pandoc::Walk for Graphviz<'a> {
                     return vec![Block::CodeBlock(attr.clone(), src)];
                 }
                 let lang = &attr.1[0];
-                if lang == "graphviz" {
-                    lazy_static::lazy_static! {
-                        static ref GRAPHVIZ_CACHE: Mutex<HashMap<u64, (PathBuf, PathBuf)>> = Mutex::new(HashMap::new());
-                    }
-
-                    let src_hash = {
-                        use std::hash::{Hash, Hasher};
-                        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-                        src.hash(&mut hasher);
-                        hasher.finish()
-                    };
-
-                    let mut cache = GRAPHVIZ_CACHE.lock().unwrap();
-
-                    let (output_name, output_path) = if cache.contains_key(&src_hash) {
-                        cache.get(&src_hash).unwrap().clone()
-                    } else {
-                        let output_name = PathBuf::from(format!("graph-{}.png", src_hash));
-                        let output_path = self.run_dir.join(&output_name);
-
-                        let mut cmd = Command::new("dot")
-                            .args(&[
-                                "-Tpng",
-                                &format!("-o{}", output_path.to_string_lossy().to_string()),
-                            ])
-                            .current_dir(self.run_dir)
-                            .stdin(Stdio::piped())
-                            .spawn()
-                            .unwrap();
-                        write!(cmd.stdin.as_mut().expect("failed to get stdin"), "{}", &src)
-                            .unwrap();
-                        let _ = cmd.wait_with_output().unwrap();
+                if lang == self.tool.lang() {
+                    let key = cache_key(&attr, &src);
+                    let output_name =
+                        PathBuf::from(format!("{}-{}.png", self.tool.output_prefix(), key.short()));
+                    let output_path = self.run_dir.join(&output_name);
 
-                        cache.insert(src_hash, (output_name.clone(), output_path.clone()));
+                    let mut cache = self.cache.lock().unwrap();
 
-                        (output_name, output_path)
-                    };
+                    if !cache.get_to_file(&key, &output_path).unwrap_or(false) {
+                        if let Err(e) = self.tool.render(&src, self.run_dir, &output_path) {
+                            return error_blocks(attr, src, e);
+                        }
+                        if let Ok(data) = fs::read(&output_path) {
+                            let _ = cache.insert(&key, &data);
+                        }
+                    }
 
                     vec![Block::Para(vec![Inline::Image(
                         Attr::null(),
@@ -179,3 +417,21 @@ impl<'a> crate::walk_pandoc::Walk for Graphviz<'a> {
         }
     }
 }
+
+/// The key of a code block is derived from its attributes (which includes the
+/// language) and its source.
+fn cache_key(attr: &Attr, src: &str) -> CacheKey {
+    let attr = serde_json::to_string(attr).unwrap();
+    CacheKey::new(&[attr.as_bytes(), src.as_bytes()])
+}
+
+/// Keeps the failing source visible and shows the error right below it.
+fn error_blocks(attr: Attr, src: String, e: io::Error) -> Vec<Block> {
+    vec![
+        Block::CodeBlock(attr, src),
+        Block::CodeBlock(
+            Attr("".to_string(), vec!["error".to_string()], vec![]),
+            e.to_string(),
+        ),
+    ]
+}
@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+/// How many earlier versions of a file are kept.
+const MAX_VERSIONS: usize = 200;
+/// Edits closer together than this are undone as one, as the editor sends
+/// every keystroke as an edit.
+const MERGE_INTERVAL: Duration = Duration::from_secs(1);
+/// Edits are not merged with the first of their group once this long has
+/// passed, so a long stretch of typing is not undone all at once.
+const MAX_GROUP_DURATION: Duration = Duration::from_secs(10);
+
+/// A source of a file which has since been replaced.
+struct Version {
+    src: String,
+    /// When the source was replaced, or for a source to redo, when the one
+    /// undone to was.
+    until: SystemTime,
+}
+
+/// The earlier sources of a file, for undoing and redoing edits on the
+/// server, so any client can undo the edits of another.
+#[derive(Default)]
+pub struct EditHistory {
+    /// Oldest first.
+    undo: VecDeque<Version>,
+    redo: Vec<Version>,
+    /// When the current group of merged edits started and when it was last
+    /// edited.
+    group: Option<(SystemTime, SystemTime)>,
+}
+
+impl EditHistory {
+    /// Records `old`, the source an edit is about to replace.
+    pub fn record(&mut self, old: &str) {
+        self.record_at(old, SystemTime::now());
+    }
+    fn record_at(&mut self, old: &str, now: SystemTime) {
+        let within = |time: SystemTime, duration: Duration| {
+            now.duration_since(time)
+                .map_or(false, |since| since < duration)
+        };
+        let group = self.group.filter(|(first, last)| {
+            within(*last, MERGE_INTERVAL) && within(*first, MAX_GROUP_DURATION)
+        });
+        self.redo.clear();
+        match group {
+            Some((first, _)) if !self.undo.is_empty() => self.group = Some((first, now)),
+            _ => {
+                self.group = Some((now, now));
+                self.push(old.to_string(), now);
+            }
+        }
+    }
+    fn push(&mut self, src: String, until: SystemTime) {
+        self.undo.push_back(Version { src, until });
+        if self.undo.len() > MAX_VERSIONS {
+            self.undo.pop_front();
+        }
+    }
+    /// The source before the last edit, if there is one, with `current` kept
+    /// to redo.
+    pub fn undo(&mut self, current: String) -> Option<String> {
+        let version = self.undo.pop_back()?;
+        // Undoing and redoing keeps when each source was replaced, so
+        // reverting to a time is not thrown off by them
+        self.redo.push(Version {
+            src: current,
+            until: version.until,
+        });
+        self.group = None;
+        Some(version.src)
+    }
+    /// The source before the last undo, if there is one.
+    pub fn redo(&mut self, current: String) -> Option<String> {
+        let version = self.redo.pop()?;
+        self.push(current, version.until);
+        self.group = None;
+        Some(version.src)
+    }
+    /// The source as it was at `time`, or the oldest one kept if `time` is
+    /// before that. Reverting is an edit of its own, so it can be undone.
+    pub fn revert_to(&mut self, time: SystemTime, current: String) -> Option<String> {
+        let src = self
+            .undo
+            .iter()
+            .find(|version| version.until > time)?
+            .src
+            .clone();
+        self.redo.clear();
+        self.push(current, SystemTime::now());
+        self.group = None;
+        Some(src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::UNIX_EPOCH;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn src(src: &str) -> Option<String> {
+        Some(src.to_string())
+    }
+
+    /// A history of edits from "0" to `n`, two seconds apart.
+    fn history(n: usize) -> EditHistory {
+        let mut history = EditHistory::default();
+        for i in 0..n {
+            history.record_at(&i.to_string(), at(2 * i as u64 + 10));
+        }
+        history
+    }
+
+    #[test]
+    fn undo_then_redo() {
+        let mut history = history(2);
+        assert_eq!(history.undo("2".to_string()), src("1"));
+        assert_eq!(history.undo("1".to_string()), src("0"));
+        assert_eq!(history.undo("0".to_string()), None);
+        assert_eq!(history.redo("0".to_string()), src("1"));
+        assert_eq!(history.redo("1".to_string()), src("2"));
+        assert_eq!(history.redo("2".to_string()), None);
+    }
+
+    #[test]
+    fn close_edits_are_undone_together() {
+        let mut history = EditHistory::default();
+        history.record_at("a", at(10));
+        history.record_at("ab", at(10));
+        assert_eq!(history.undo("abc".to_string()), src("a"));
+        assert_eq!(history.undo("a".to_string()), None);
+    }
+
+    #[test]
+    fn long_typing_is_split_into_groups() {
+        // An edit every half second keeps each one close to the last
+        let mut history = EditHistory::default();
+        for i in 0..30 {
+            history.record_at(&i.to_string(), UNIX_EPOCH + Duration::from_millis(500 * i));
+        }
+        // A new group starts after ten seconds, at the edit replacing "20"
+        assert_eq!(history.undo("30".to_string()), src("20"));
+        assert_eq!(history.undo("20".to_string()), src("0"));
+        assert_eq!(history.undo("0".to_string()), None);
+    }
+
+    #[test]
+    fn edit_clears_redo() {
+        let mut history = history(2);
+        assert_eq!(history.undo("2".to_string()), src("1"));
+        history.record_at("1", at(100));
+        assert_eq!(history.redo("x".to_string()), None);
+        assert_eq!(history.undo("x".to_string()), src("1"));
+    }
+
+    #[test]
+    fn oldest_versions_are_evicted() {
+        let mut history = history(MAX_VERSIONS + 1);
+        let mut current = (MAX_VERSIONS + 1).to_string();
+        for i in (1..=MAX_VERSIONS).rev() {
+            current = history.undo(current).unwrap();
+            assert_eq!(current, i.to_string());
+        }
+        assert_eq!(history.undo(current), None);
+    }
+
+    #[test]
+    fn revert_to_time() {
+        let mut history = history(3);
+        // "1" was replaced at 12
+        assert_eq!(history.revert_to(at(11), "3".to_string()), src("1"));
+        // Reverting can be undone
+        assert_eq!(history.undo("1".to_string()), src("3"));
+    }
+
+    #[test]
+    fn revert_to_before_oldest() {
+        let mut history = history(3);
+        assert_eq!(history.revert_to(at(0), "3".to_string()), src("0"));
+    }
+
+    #[test]
+    fn revert_to_after_newest() {
+        // Nothing was replaced after the last edit
+        let mut history = history(3);
+        assert_eq!(history.revert_to(at(100), "3".to_string()), None);
+    }
+}
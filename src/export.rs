@@ -0,0 +1,295 @@
+use pandoc_types::definition::Pandoc;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::citations::CitationSettings;
+
+/// How often a running export checks whether it finished or was cancelled.
+const JOB_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often a running export reports progress.
+const JOB_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Pdf,
+    Html,
+    Docx,
+    Odt,
+    Epub,
+    Latex,
+}
+
+impl ExportFormat {
+    pub fn from_extension(ext: &str) -> Option<ExportFormat> {
+        match ext {
+            "pdf" => Some(ExportFormat::Pdf),
+            "html" => Some(ExportFormat::Html),
+            "docx" => Some(ExportFormat::Docx),
+            "odt" => Some(ExportFormat::Odt),
+            "epub" => Some(ExportFormat::Epub),
+            "tex" => Some(ExportFormat::Latex),
+            _ => None,
+        }
+    }
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Html => "html",
+            ExportFormat::Docx => "docx",
+            ExportFormat::Odt => "odt",
+            ExportFormat::Epub => "epub",
+            ExportFormat::Latex => "tex",
+        }
+    }
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Pdf => "application/pdf",
+            ExportFormat::Html => "text/html",
+            ExportFormat::Docx => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
+            ExportFormat::Odt => "application/vnd.oasis.opendocument.text",
+            ExportFormat::Epub => "application/epub+zip",
+            ExportFormat::Latex => "application/x-tex",
+        }
+    }
+    /// Whether pandoc styles this format after a reference document.
+    fn uses_reference_doc(self) -> bool {
+        match self {
+            ExportFormat::Docx | ExportFormat::Odt => true,
+            _ => false,
+        }
+    }
+    /// Whether the format is rendered through LaTeX, which is what the header
+    /// includes are written in.
+    fn uses_latex(self) -> bool {
+        match self {
+            ExportFormat::Pdf | ExportFormat::Latex => true,
+            _ => false,
+        }
+    }
+    /// The pandoc writer, or `None` when pandoc should infer it from the
+    /// output file, as it does for pdfs.
+    fn pandoc_format(self) -> Option<&'static str> {
+        match self {
+            ExportFormat::Pdf => None,
+            ExportFormat::Html => Some("html5"),
+            ExportFormat::Docx => Some("docx"),
+            ExportFormat::Odt => Some("odt"),
+            ExportFormat::Epub => Some("epub"),
+            ExportFormat::Latex => Some("latex"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ExportOptions {
+    /// Produce a complete document rather than a fragment. Only matters for
+    /// text formats such as html and latex.
+    pub standalone: bool,
+    /// Embed images and styles, so the output is a single file.
+    pub self_contained: bool,
+    /// A pandoc template, relative to the project directory.
+    pub template: Option<PathBuf>,
+}
+
+/// Export settings stored in the project config. Paths are relative to the
+/// project directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ExportSettings {
+    /// Pandoc templates to use for each format.
+    pub templates: HashMap<ExportFormat, PathBuf>,
+    /// Styles docx and odt output.
+    pub reference_doc: Option<PathBuf>,
+    /// LaTeX files included at the end of the header of pdf and LaTeX output,
+    /// such as extra packages.
+    pub include_in_header: Vec<PathBuf>,
+}
+
+impl ExportSettings {
+    /// The settings with all paths resolved against `dir`.
+    pub fn resolve(&self, dir: &Path) -> ExportSettings {
+        ExportSettings {
+            templates: self
+                .templates
+                .iter()
+                .map(|(format, path)| (*format, dir.join(path)))
+                .collect(),
+            reference_doc: self.reference_doc.as_ref().map(|path| dir.join(path)),
+            include_in_header: self
+                .include_in_header
+                .iter()
+                .map(|path| dir.join(path))
+                .collect(),
+        }
+    }
+}
+
+/// Everything needed to export a project, detached from the project so it
+/// can be rendered on another thread.
+pub struct ExportInput {
+    pub pandoc: Pandoc,
+    pub root: PathBuf,
+    /// Where pandoc looks for images.
+    pub resource_path: Vec<PathBuf>,
+    pub format: ExportFormat,
+    pub options: ExportOptions,
+    pub settings: ExportSettings,
+    pub citations: CitationSettings,
+    /// Citation settings are relative to this.
+    pub project_dir: PathBuf,
+}
+
+impl ExportInput {
+    /// Starts pandoc writing to `output_path`, with its error output going to
+    /// `log_path`.
+    pub fn spawn(&self, output_path: &Path, log_path: &Path) -> io::Result<Child> {
+        let resource_path = std::env::join_paths(&self.resource_path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut resource_path_arg = OsString::from("--resource-path=");
+        resource_path_arg.push(resource_path);
+
+        let mut args: Vec<OsString> = vec![
+            "-f".into(),
+            "json".into(),
+            "-o".into(),
+            output_path.into(),
+            resource_path_arg,
+        ];
+        if let Some(to) = self.format.pandoc_format() {
+            args.push("-t".into());
+            args.push(to.into());
+        }
+        if self.options.standalone {
+            args.push("--standalone".into());
+        }
+        if self.options.self_contained {
+            args.push("--self-contained".into());
+        }
+        // A template given for this export wins over the project's template
+        let template = self
+            .options
+            .template
+            .as_ref()
+            .or_else(|| self.settings.templates.get(&self.format));
+        if let Some(template) = template {
+            args.push(path_arg("--template=", template));
+        }
+        if let Some(reference_doc) = &self.settings.reference_doc {
+            if self.format.uses_reference_doc() {
+                args.push(path_arg("--reference-doc=", reference_doc));
+            }
+        }
+        args.extend(self.citations.pandoc_args(&self.project_dir));
+        if self.format.uses_latex() {
+            for header in &self.settings.include_in_header {
+                args.push(path_arg("--include-in-header=", header));
+            }
+        }
+
+        let mut cmd = Command::new("pandoc")
+            .args(&args)
+            .current_dir(&self.root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::from(fs::File::create(log_path)?))
+            .spawn()?;
+        let src = serde_json::to_string(&self.pandoc).unwrap();
+        // Dropping stdin closes it, letting pandoc know the input is done
+        let mut stdin = cmd.stdin.take().expect("failed to get stdin");
+        write!(stdin, "{}", &src)?;
+
+        Ok(cmd)
+    }
+    /// Renders the document, returning pandoc's error output if it fails.
+    pub fn run(&self, output_path: &Path) -> io::Result<()> {
+        let log_path = output_path.with_extension("log");
+        let status = self.spawn(output_path, &log_path)?.wait()?;
+        check_status(status, &log_path)
+    }
+}
+
+/// Deletes the output of an export along with its log, once it is no
+/// longer needed.
+pub fn remove_output(output_path: &Path) {
+    for path in &[output_path.to_path_buf(), output_path.with_extension("log")] {
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                println!("failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn path_arg(flag: &str, path: &Path) -> OsString {
+    let mut arg = OsString::from(flag);
+    arg.push(path);
+    arg
+}
+
+/// Turns an unsuccessful exit into an error carrying the log.
+pub fn check_status(status: ExitStatus, log_path: &Path) -> io::Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            fs::read_to_string(log_path).unwrap_or_else(|_| status.to_string()),
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JobId {
+    pub job_id: u64,
+}
+
+/// How a finished export job ended, when it did not fail.
+pub enum JobEnd {
+    Complete,
+    Cancelled,
+}
+
+/// Renders the document, calling `progress` with the elapsed time while
+/// pandoc is running. Setting `cancel` kills pandoc.
+pub fn run_job(
+    input: &ExportInput,
+    output_path: &Path,
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(Duration),
+) -> io::Result<JobEnd> {
+    let log_path = output_path.with_extension("log");
+    let start = Instant::now();
+    let mut last_progress = start;
+    let mut child = input.spawn(output_path, &log_path)?;
+
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            child.kill()?;
+            child.wait()?;
+            return Ok(JobEnd::Cancelled);
+        }
+        if let Some(status) = child.try_wait()? {
+            check_status(status, &log_path)?;
+            return Ok(JobEnd::Complete);
+        }
+        if last_progress.elapsed() >= JOB_PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            progress(start.elapsed());
+        }
+        thread::sleep(JOB_POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,104 @@
+use serde::Serialize;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::project::asset;
+
+/// A commit in the history of a project.
+#[derive(Serialize, Debug, Clone)]
+pub struct Revision {
+    pub id: String,
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    pub message: String,
+}
+
+/// Runs git in `dir`, returning what it printed. Commits are made as riport,
+/// so history works without git being configured on the server.
+fn git(dir: &Path, args: &[&str]) -> io::Result<Vec<u8>> {
+    let out = Command::new("git")
+        .args(&[
+            "-c",
+            "user.name=riport",
+            "-c",
+            "user.email=riport@localhost",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .output()?;
+    if !out.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        ));
+    }
+    Ok(out.stdout)
+}
+
+/// Whether the project in `dir` keeps its history in git.
+pub fn is_enabled(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+/// Creates a repository for the project in `dir`. The compile cache is kept
+/// out of it.
+pub fn init(dir: &Path) -> io::Result<()> {
+    git(dir, &["init"])?;
+    fs::write(dir.join(".git").join("info").join("exclude"), ".riport/\n")
+}
+
+/// Commits everything in `dir`, returning the new revision, or nothing when
+/// nothing changed since the last one.
+pub fn commit(dir: &Path, message: &str) -> io::Result<Option<Revision>> {
+    git(dir, &["add", "-A"])?;
+    if git(dir, &["status", "--porcelain"])?.is_empty() {
+        return Ok(None);
+    }
+    git(dir, &["commit", "-q", "-m", message])?;
+    Ok(log(dir)?.into_iter().next())
+}
+
+/// The revisions of the project, newest first.
+pub fn log(dir: &Path) -> io::Result<Vec<Revision>> {
+    // A repository without commits has no log
+    if git(dir, &["rev-parse", "-q", "--verify", "HEAD"]).is_err() {
+        return Ok(vec![]);
+    }
+    let out = git(dir, &["log", "--format=%H%x1f%at%x1f%s"])?;
+    Ok(String::from_utf8_lossy(&out)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\u{1f}');
+            Some(Revision {
+                id: fields.next()?.to_string(),
+                time: fields.next()?.parse().ok()?,
+                message: fields.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// The contents of the file at `path` in `revision`.
+pub fn show(dir: &Path, revision: &str, path: &Path) -> io::Result<String> {
+    // Only commit hashes are accepted, so a revision is never taken for an
+    // option of git
+    let is_hash = !revision.is_empty() && revision.chars().all(|c| c.is_ascii_hexdigit());
+    if !is_hash || !asset::is_project_path(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid revision {:?} of {}", revision, path.display()),
+        ));
+    }
+    let path: Vec<_> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let out = git(
+        dir,
+        &["show", &format!("{}:./{}", revision, path.join("/"))],
+    )?;
+    String::from_utf8(out).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
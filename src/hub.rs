@@ -5,28 +5,39 @@ use futures::prelude::*;
 use std::collections::HashMap;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::archive;
 use crate::client::{Client, ClientId};
+use crate::project::asset;
 use crate::project::file::FileId;
+use crate::project::template;
 use crate::project::{Project, ProjectId, ProjectInfo};
-use crate::project_actor::{GetInfo, ProjectActor};
+use crate::project_actor::{GetInfo, ProjectActor, ReloadPath};
+use crate::watch::{self, PathChanged};
 
 use crate::s2c::*;
 
 pub struct Hub {
     projects_path: PathBuf,
+    /// Directories can only be imported from below this one. Without it only
+    /// directories in the projects path can be imported.
+    import_root: Option<PathBuf>,
     connections: HashMap<ClientId, Addr<Client>>,
     projects: HashMap<ProjectId, Addr<ProjectActor>>,
+    /// The projects by the name of their directory in the projects path.
+    project_dirs: HashMap<PathBuf, ProjectId>,
     tmpdir: tempdir::TempDir,
 }
 
 impl Hub {
-    pub fn new(projects_path: PathBuf) -> io::Result<Hub> {
+    pub fn new(projects_path: PathBuf, import_root: Option<PathBuf>) -> io::Result<Hub> {
         let mut hub = Hub {
             projects_path,
+            import_root,
             connections: HashMap::new(),
             projects: HashMap::new(),
+            project_dirs: HashMap::new(),
             tmpdir: tempdir::TempDir::new("hub").unwrap(),
         };
 
@@ -38,7 +49,11 @@ impl Hub {
             if path.is_dir() {
                 let name = path.file_name().unwrap().to_string_lossy().to_string();
                 let path = hub.projects_path.join(&name);
-                hub.load_project(name, path);
+                // A broken project is left out, rather than keeping the
+                // server from starting
+                if let Err(e) = hub.load_project(name, path.clone()) {
+                    println!("failed to load project at {:?}: {}", path, e);
+                }
             } else {
                 // TODO
             }
@@ -63,34 +78,181 @@ impl Hub {
                 .collect()
         })
     }
-    fn create_project(&mut self, name: String) -> (ProjectId, Addr<ProjectActor>) {
+    /// Creates a project in a new directory, so an existing project with the
+    /// same name is left alone.
+    fn create_project(
+        &mut self,
+        name: String,
+        template: Option<String>,
+    ) -> io::Result<(ProjectId, Addr<ProjectActor>)> {
         let id = ProjectId {
             project_id: self.projects.len() as _,
         };
-        let path = self.projects_path.join(&name);
+        let path = self.unused_project_path(&name)?;
+        let dir = PathBuf::from(path.file_name().unwrap());
         let project_tmpdir = self.tmpdir.path().join(&format!("{}", id.project_id));
-        fs::create_dir_all(&project_tmpdir).unwrap();
-        let project = ProjectActor::new(id, name, path, project_tmpdir);
+        fs::create_dir_all(&project_tmpdir)?;
+        let project = match ProjectActor::new(id, name, path.clone(), project_tmpdir, template) {
+            Ok(project) => project,
+            Err(e) => {
+                // Nothing of a project which failed to be created is left behind
+                if path.exists() {
+                    if let Err(e) = fs::remove_dir_all(&path) {
+                        println!("failed to remove {:?}: {}", path, e);
+                    }
+                }
+                return Err(e);
+            }
+        };
         self.projects.insert(id, project.clone());
+        self.project_dirs.insert(dir, id);
 
-        (id, project)
+        Ok((id, project))
     }
-    fn load_project(&mut self, name: String, path: PathBuf) -> (ProjectId, Addr<ProjectActor>) {
+    fn load_project(
+        &mut self,
+        name: String,
+        path: PathBuf,
+    ) -> io::Result<(ProjectId, Addr<ProjectActor>)> {
         println!("loading project at {:?}", path);
         let id = ProjectId {
             project_id: self.projects.len() as _,
         };
         let project_tmpdir = self.tmpdir.path().join(&format!("{}", id.project_id));
-        fs::create_dir_all(&project_tmpdir).unwrap();
-        let project = ProjectActor::read_from_disk(path.to_owned(), project_tmpdir);
+        fs::create_dir_all(&project_tmpdir)?;
+        let project = ProjectActor::read_from_disk(path.to_owned(), project_tmpdir)?;
         self.projects.insert(id, project.clone());
+        if let Some(dir) = path.file_name() {
+            self.project_dirs.insert(PathBuf::from(dir), id);
+        }
 
-        (id, project)
+        Ok((id, project))
+    }
+    /// A directory in the projects path for a project named `name`, which is
+    /// not in use. Names which are not a single directory name are rejected.
+    fn unused_project_path(&self, name: &str) -> io::Result<PathBuf> {
+        let name_path = Path::new(name);
+        if name_path.components().count() != 1 || !asset::is_project_path(name_path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid project name {:?}", name),
+            ));
+        }
+        let mut path = self.projects_path.join(name);
+        let mut n = 2;
+        while path.exists() {
+            path = self.projects_path.join(format!("{}-{}", name, n));
+            n += 1;
+        }
+        Ok(path)
     }
+    /// Loads a directory in the projects path as a new project, writing a
+    /// config for it first.
+    fn import_project(&mut self, name: String, path: PathBuf) -> io::Result<ProjectId> {
+        let id = ProjectId {
+            project_id: self.projects.len() as _,
+        };
+        Project::prepare_import(&path, id, name.clone())?;
+        let (id, _) = self.load_project(name, path)?;
+        Ok(id)
+    }
+    fn broadcast_projects(&mut self, ctx: &mut Context<Self>) {
+        self.generate_project_info_list(ctx)
+            .into_actor(self)
+            .then(|res, act, _| {
+                if let Ok(list) = res {
+                    for addr in act.connections.values() {
+                        addr.do_send(Server2Client::Projects { list: list.clone() });
+                    }
+                }
+
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+}
+
+/// Splits a path in the projects path into the directory of its project
+/// and the rest of it.
+fn split_project_dir(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let mut components = path.components();
+    let dir = PathBuf::from(components.next()?.as_os_str());
+    Some((dir, components.as_path().to_path_buf()))
+}
+
+/// Copies the files in `from` to `to`, leaving out hidden files. Symlinks
+/// are left out too, as they could lead outside of `from`.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for e in fs::read_dir(from)? {
+        let e = e?;
+        if e.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let file_type = e.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&e.path(), &to.join(e.file_name()))?;
+        } else if file_type.is_file() {
+            fs::copy(&e.path(), to.join(e.file_name()))?;
+        }
+    }
+    Ok(())
 }
 
 impl Actor for Hub {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Err(e) = watch::watch(&self.projects_path, ctx.address().recipient()) {
+            println!("failed to watch {:?}: {}", self.projects_path, e);
+        }
+    }
+}
+
+/// Passes changes on to the project they were made in, and loads directories
+/// put in the projects path as new projects.
+impl Handler<PathChanged> for Hub {
+    type Result = ();
+    fn handle(&mut self, msg: PathChanged, ctx: &mut Context<Self>) {
+        let (dir, rest) = match split_project_dir(&msg.path) {
+            Some(split) => split,
+            None => return,
+        };
+        // Only a rename within a project moves a file, anything else is a
+        // change to each of the paths
+        let from = match msg.from.as_ref().and_then(|from| split_project_dir(from)) {
+            Some((from_dir, from_rest)) if from_dir == dir => Some(from_rest),
+            Some(_) => {
+                let from = msg.from.clone().unwrap();
+                self.handle(
+                    PathChanged {
+                        path: from,
+                        from: None,
+                    },
+                    ctx,
+                );
+                None
+            }
+            None => None,
+        };
+
+        if let Some(id) = self.project_dirs.get(&dir) {
+            if !rest.as_os_str().is_empty() {
+                self.projects[id].do_send(ReloadPath { path: rest, from });
+            }
+            return;
+        }
+
+        let path = self.projects_path.join(&dir);
+        if !path.is_dir() {
+            return;
+        }
+        let name = dir.to_string_lossy().to_string();
+        match self.import_project(name, path.clone()) {
+            Ok(_) => self.broadcast_projects(ctx),
+            Err(e) => println!("failed to load project at {:?}: {}", path, e),
+        }
+    }
 }
 
 pub struct Connect {
@@ -107,6 +269,11 @@ impl Handler<Connect> for Hub {
     fn handle(&mut self, msg: Connect, ctx: &mut Context<Self>) {
         self.connections.insert(msg.client_id, msg.adder.clone());
 
+        match template::builtin_templates() {
+            Ok(list) => msg.adder.do_send(Server2Client::Templates { list }),
+            Err(e) => println!("failed to list templates: {}", e),
+        }
+
         self.generate_project_info_list(ctx)
             .into_actor(self)
             .then(move |res, _act, _| {
@@ -123,23 +290,123 @@ impl Handler<Connect> for Hub {
 
 pub struct CreateProject {
     pub name: String,
+    /// A built-in template to start from.
+    pub template: Option<String>,
 }
 
 impl Message for CreateProject {
-    type Result = CreateProjectRes;
+    type Result = Result<ProjectId, String>;
 }
 
-#[derive(MessageResponse)]
-pub struct CreateProjectRes {
-    pub id: ProjectId,
+impl Handler<CreateProject> for Hub {
+    type Result = Result<ProjectId, String>;
+    fn handle(&mut self, msg: CreateProject, ctx: &mut Context<Self>) -> Self::Result {
+        let name = msg.name.clone();
+        let (id, _) = self
+            .create_project(msg.name, msg.template)
+            .map_err(|e| format!("failed to create project {:?}: {}", name, e))?;
+
+        self.broadcast_projects(ctx);
+
+        Ok(id)
+    }
 }
 
-impl Handler<CreateProject> for Hub {
-    type Result = CreateProjectRes;
-    fn handle(&mut self, msg: CreateProject, _: &mut Context<Self>) -> CreateProjectRes {
-        let (id, _) = self.create_project(msg.name);
+/// Imports a project from a zip archive.
+pub struct ImportArchive {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl Message for ImportArchive {
+    type Result = Result<ProjectId, String>;
+}
+
+impl Handler<ImportArchive> for Hub {
+    type Result = Result<ProjectId, String>;
+    fn handle(&mut self, msg: ImportArchive, ctx: &mut Context<Self>) -> Self::Result {
+        let id = self
+            .unused_project_path(&msg.name)
+            .and_then(|path| {
+                let imported = archive::unzip(&msg.data, &path)
+                    .and_then(|()| self.import_project(msg.name, path.clone()));
+                // Nothing of a failed import is left behind
+                if imported.is_err() {
+                    if let Err(e) = fs::remove_dir_all(&path) {
+                        println!("failed to remove {:?}: {}", path, e);
+                    }
+                }
+                imported
+            })
+            .map_err(|e| format!("failed to import archive: {}", e))?;
+
+        self.broadcast_projects(ctx);
+
+        Ok(id)
+    }
+}
+
+/// Imports a directory of markdown files as a project. Directories outside of
+/// the projects path are copied into it.
+pub struct ImportDirectory {
+    pub path: PathBuf,
+    /// Defaults to the name of the directory.
+    pub name: Option<String>,
+}
+
+impl Message for ImportDirectory {
+    type Result = Result<ProjectId, String>;
+}
+
+impl Handler<ImportDirectory> for Hub {
+    type Result = Result<ProjectId, String>;
+    fn handle(&mut self, msg: ImportDirectory, ctx: &mut Context<Self>) -> Self::Result {
+        let name = match msg.name.or_else(|| {
+            msg.path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        }) {
+            Some(name) => name,
+            None => return Err(format!("no name for {:?}", msg.path)),
+        };
+
+        // Symlinks and `..` are resolved before checking where the directory
+        // is
+        let path = msg
+            .path
+            .canonicalize()
+            .map_err(|e| format!("failed to import {:?}: {}", msg.path, e))?;
+        let projects_path = self.projects_path.canonicalize().ok();
+        let in_projects_path = path.parent().map(Path::to_path_buf) == projects_path;
+        let in_import_root = self
+            .import_root
+            .as_ref()
+            .and_then(|root| root.canonicalize().ok())
+            .map_or(false, |root| path.starts_with(root));
+
+        let id = if in_projects_path {
+            if path.join("config.json").exists() {
+                return Err(format!("{:?} is already a project", msg.path));
+            }
+            // A directory put in the projects path is imported where it is,
+            // in case it was not picked up as it was created
+            self.import_project(name, path)
+        } else if in_import_root {
+            self.unused_project_path(&name).and_then(|to| {
+                copy_dir(&path, &to)?;
+                self.import_project(name, to)
+            })
+        } else {
+            return Err(format!(
+                "{:?} is outside of the directories projects can be imported from",
+                msg.path
+            ));
+        }
+        .map_err(|e| format!("failed to import {:?}: {}", msg.path, e))?;
+
+        self.broadcast_projects(ctx);
 
-        CreateProjectRes { id }
+        Ok(id)
     }
 }
 
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::incremental::{closes_fence, fence_run};
+
+/// A directive pulling another file into the source, such as
+/// `!include appendix/data.md` or a fenced block opened with
+/// ```` ```{include="appendix/data.md"} ````.
+struct Directive<'a> {
+    /// The included file, relative to the project directory.
+    path: &'a str,
+    /// The lines of the source the directive spans.
+    start: usize,
+    end: usize,
+}
+
+fn directives(src: &str) -> Vec<Directive> {
+    let lines: Vec<&str> = src.lines().collect();
+    let mut directives = vec![];
+    // The fence of the code block the line is in, if any
+    let mut code: Option<(char, usize)> = None;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].trim();
+        if let Some((c, len)) = code {
+            if closes_fence(line, c, len) {
+                code = None;
+            }
+        } else if let Some((c, len)) = fence_run(line) {
+            match include_attribute(line) {
+                Some(path) => {
+                    // The block spans up to and including its closing fence
+                    let end = lines[i + 1..]
+                        .iter()
+                        .position(|l| closes_fence(l.trim(), c, len))
+                        .map_or(lines.len(), |p| i + p + 2);
+                    directives.push(Directive {
+                        path,
+                        start: i,
+                        end,
+                    });
+                    i = end;
+                    continue;
+                }
+                None => code = Some((c, len)),
+            }
+        } else if line.starts_with("!include ") {
+            let path = line["!include ".len()..].trim().trim_matches('"');
+            if !path.is_empty() {
+                directives.push(Directive {
+                    path,
+                    start: i,
+                    end: i + 1,
+                });
+            }
+        }
+        i += 1;
+    }
+    directives
+}
+
+/// The path in a fence like ```` ```{include="data.md"} ````.
+fn include_attribute(fence: &str) -> Option<&str> {
+    let start = fence.find("include=\"")? + "include=\"".len();
+    let len = fence[start..].find('"')?;
+    Some(&fence[start..start + len]).filter(|path| !path.is_empty())
+}
+
+/// Paths like `./data.md` and `data.md` name the same file.
+fn normalize(path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+/// The source of a file with its includes expanded.
+pub struct Expanded {
+    pub src: String,
+    /// Includes which could not be expanded.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Expands the includes of the file at `path`, recursively, given the
+/// sources of all files in the project.
+pub fn expand(path: &Path, sources: &HashMap<PathBuf, String>) -> Expanded {
+    let mut stack = vec![path.to_path_buf()];
+    let mut errors = vec![];
+    let src = expand_src(&sources[path], sources, &mut stack, None, &mut errors);
+
+    Expanded {
+        src,
+        diagnostics: errors
+            .into_iter()
+            .map(|(line, message)| Diagnostic {
+                severity: Severity::Error,
+                message,
+                line: Some(line),
+            })
+            .collect(),
+    }
+}
+
+/// Errors in nested includes are reported on the line of the outermost
+/// directive, as that is the line in the file being expanded.
+fn expand_src(
+    src: &str,
+    sources: &HashMap<PathBuf, String>,
+    stack: &mut Vec<PathBuf>,
+    line: Option<usize>,
+    errors: &mut Vec<(usize, String)>,
+) -> String {
+    let directives = directives(src);
+    if directives.is_empty() {
+        return src.to_string();
+    }
+
+    let lines: Vec<&str> = src.lines().collect();
+    let mut out = String::new();
+    let mut last = 0;
+    for directive in directives {
+        for l in &lines[last..directive.start] {
+            out.push_str(l);
+            out.push('\n');
+        }
+        last = directive.end;
+
+        let line = line.unwrap_or(directive.start + 1);
+        let target = normalize(directive.path);
+        if stack.contains(&target) {
+            let mut cycle: Vec<String> = stack
+                .iter()
+                .skip_while(|p| **p != target)
+                .map(|p| p.display().to_string())
+                .collect();
+            cycle.push(target.display().to_string());
+            errors.push((line, format!("include cycle: {}", cycle.join(" -> "))));
+            continue;
+        }
+        let included = match sources.get(&target) {
+            Some(included) => included,
+            None => {
+                errors.push((line, format!("no file {:?} to include", directive.path)));
+                continue;
+            }
+        };
+
+        stack.push(target);
+        let included = expand_src(included, sources, stack, Some(line), errors);
+        stack.pop();
+
+        // Blank lines keep the included blocks apart from the ones around them
+        out.push('\n');
+        out.push_str(&included);
+        out.push_str("\n\n");
+    }
+    for l in &lines[last..] {
+        out.push_str(l);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(files: &[(&str, &str)]) -> HashMap<PathBuf, String> {
+        files
+            .iter()
+            .map(|(path, src)| (PathBuf::from(path), src.to_string()))
+            .collect()
+    }
+
+    fn errors(expanded: &Expanded) -> Vec<(Option<usize>, &str)> {
+        expanded
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn nested_includes() {
+        let sources = sources(&[
+            ("index.md", "# Index\n\n!include a.md\n\nend"),
+            ("a.md", "a\n\n```{include=\"./b/b.md\"}\n```"),
+            ("b/b.md", "b"),
+        ]);
+        let expanded = expand(Path::new("index.md"), &sources);
+        assert!(expanded.diagnostics.is_empty());
+        let words: Vec<&str> = expanded.src.split_whitespace().collect();
+        assert_eq!(words, ["#", "Index", "a", "b", "end"]);
+    }
+
+    #[test]
+    fn self_include() {
+        let sources = sources(&[("a.md", "a\n!include a.md")]);
+        let expanded = expand(Path::new("a.md"), &sources);
+        assert_eq!(
+            errors(&expanded),
+            [(Some(2), "include cycle: a.md -> a.md")]
+        );
+        assert_eq!(expanded.src.trim(), "a");
+    }
+
+    #[test]
+    fn include_cycle() {
+        let sources = sources(&[
+            ("a.md", "a\n\n!include b.md"),
+            ("b.md", "b\n\n!include ./a.md"),
+        ]);
+        let expanded = expand(Path::new("a.md"), &sources);
+        // Reported on the directive in a.md, which is the file expanded
+        assert_eq!(
+            errors(&expanded),
+            [(Some(3), "include cycle: a.md -> b.md -> a.md")]
+        );
+        let words: Vec<&str> = expanded.src.split_whitespace().collect();
+        assert_eq!(words, ["a", "b"]);
+    }
+
+    #[test]
+    fn missing_include() {
+        let sources = sources(&[("a.md", "a\n\n!include missing.md\n\nb")]);
+        let expanded = expand(Path::new("a.md"), &sources);
+        assert_eq!(
+            errors(&expanded),
+            [(Some(3), "no file \"missing.md\" to include")]
+        );
+        let words: Vec<&str> = expanded.src.split_whitespace().collect();
+        assert_eq!(words, ["a", "b"]);
+    }
+
+    #[test]
+    fn includes_in_code_are_kept() {
+        let sources = sources(&[
+            (
+                "a.md",
+                "~~~\n!include b.md\n~~~\n\n````\n```\n!include b.md\n````\n\n!include b.md",
+            ),
+            ("b.md", "b"),
+        ]);
+        let expanded = expand(Path::new("a.md"), &sources);
+        assert!(expanded.diagnostics.is_empty());
+        let lines: Vec<&str> = expanded.src.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(
+            lines,
+            [
+                "~~~",
+                "!include b.md",
+                "~~~",
+                "````",
+                "```",
+                "!include b.md",
+                "````",
+                "b"
+            ]
+        );
+    }
+
+    #[test]
+    fn fenced_include_ends_at_matching_fence() {
+        let sources = sources(&[
+            ("a.md", "````{include=\"b.md\"}\n```\n````\nafter"),
+            ("b.md", "b"),
+        ]);
+        let expanded = expand(Path::new("a.md"), &sources);
+        let words: Vec<&str> = expanded.src.split_whitespace().collect();
+        assert_eq!(words, ["b", "after"]);
+    }
+}
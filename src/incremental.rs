@@ -0,0 +1,378 @@
+use pandoc_types::definition::{Block, Meta, Pandoc};
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io;
+
+use crate::doc::{CompileContext, Input};
+use crate::project::file::Doc;
+
+/// Placed between chunks which are parsed together, so the parsed blocks can be
+/// split up again.
+const CHUNK_SEPARATOR: &str = "<!-- riport-chunk -->";
+
+/// A construct which may hold blank lines, so it is kept in one chunk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Open {
+    /// A code fence of a character and length.
+    Fence(char, usize),
+    /// Fenced divs, which nest, by how deep they are.
+    Div(usize),
+    /// Display math between `$$`.
+    Math,
+    /// A YAML metadata block at the start of the source.
+    Metadata,
+}
+
+/// Splits source into top-level chunks, separated by blank lines, which can
+/// be compiled independently of each other.
+///
+/// Code blocks, fenced divs, display math and a leading metadata block are
+/// kept whole, and indented lines after a blank line stay with the chunk
+/// before them, as they continue lists and the like. Reference links and
+/// footnotes can point anywhere in the file, and definition lists and raw
+/// HTML can span blank lines in too many ways to follow, so sources using
+/// them are kept as a single chunk.
+pub fn split_chunks(src: &str) -> Vec<String> {
+    let single = src.lines().any(|line| {
+        let line = line.trim_start();
+        (line.starts_with('[') && line.contains("]:")) || is_definition(line) || is_html(line)
+    });
+    if single {
+        return vec![src.to_string()];
+    }
+
+    let lines: Vec<&str> = src.lines().collect();
+    let mut chunks: Vec<String> = vec![];
+    let mut current = String::new();
+    let mut open: Option<Open> = None;
+    let mut blank_lines = 0;
+
+    // Pandoc only takes `---` for the start of metadata when it is not
+    // followed by a blank line
+    if lines.len() > 1 && lines[0].trim_end() == "---" && !lines[1].trim().is_empty() {
+        open = Some(Open::Metadata);
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+
+        if open.is_none() && line.trim().is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+
+        let continues = open.is_some()
+            || line.starts_with(' ')
+            || line.starts_with('\t')
+            || (is_list_item(trimmed) && is_list_item(&current));
+        if blank_lines > 0 && !continues && !current.is_empty() {
+            chunks.push(std::mem::replace(&mut current, String::new()));
+        }
+        if !current.is_empty() {
+            for _ in 0..blank_lines {
+                current.push('\n');
+            }
+        }
+        blank_lines = 0;
+
+        current.push_str(line);
+        current.push('\n');
+
+        open = match open {
+            Some(Open::Fence(c, len)) if closes_fence(trimmed, c, len) => None,
+            Some(Open::Div(depth)) => match div_fence(trimmed) {
+                Some(true) => Some(Open::Div(depth + 1)),
+                Some(false) if depth == 1 => None,
+                Some(false) => Some(Open::Div(depth - 1)),
+                None => open,
+            },
+            Some(Open::Math) if trimmed.contains("$$") => None,
+            Some(Open::Metadata) if i > 0 && (trimmed == "---" || trimmed == "...") => None,
+            Some(open) => Some(open),
+            None => {
+                if let Some((c, len)) = fence_run(trimmed) {
+                    Some(Open::Fence(c, len))
+                } else if div_fence(trimmed) == Some(true) {
+                    Some(Open::Div(1))
+                } else if trimmed.starts_with("$$") && trimmed.matches("$$").count() % 2 == 1 {
+                    Some(Open::Math)
+                } else {
+                    None
+                }
+            }
+        };
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// The link reference definitions of a source, such as `[riport]: https://..`.
+/// Footnotes are left out.
+pub fn reference_definitions(src: &str) -> Vec<&str> {
+    src.lines()
+        .filter(|line| {
+            let indent = line.len() - line.trim_start().len();
+            let line = line.trim_start();
+            indent < 4
+                && line.starts_with('[')
+                && !line.starts_with("[^")
+                && line.find("]:").map_or(false, |end| end > 1)
+        })
+        .collect()
+}
+
+/// As in CommonMark, a fence is closed by a run of the same character which
+/// is at least as long, with nothing after it.
+pub fn closes_fence(line: &str, c: char, len: usize) -> bool {
+    fence_run(line).map_or(false, |(closing_c, closing_len)| {
+        closing_c == c && closing_len >= len && line[closing_len..].trim().is_empty()
+    })
+}
+
+/// Whether a line opens a fenced div, like `::: warning`, or closes one.
+fn div_fence(line: &str) -> Option<bool> {
+    let colons = line.chars().take_while(|c| *c == ':').count();
+    if colons < 3 {
+        return None;
+    }
+    Some(!line[colons..].trim().is_empty())
+}
+
+/// A definition of a term in a definition list, like `: the definition`.
+fn is_definition(line: &str) -> bool {
+    line.starts_with(": ") || line.starts_with(":\t") || line.starts_with("~ ")
+}
+
+/// The start of a raw HTML block or comment, like `<div>` or `<!--`.
+fn is_html(line: &str) -> bool {
+    let mut chars = line.chars();
+    chars.next() == Some('<')
+        && chars
+            .next()
+            .map_or(false, |c| c.is_ascii_alphabetic() || c == '/' || c == '!')
+}
+
+/// The character and length of the code fence a line starts with, if any.
+pub fn fence_run(line: &str) -> Option<(char, usize)> {
+    let c = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|d| *d == c).count();
+    if len >= 3 {
+        Some((c, len))
+    } else {
+        None
+    }
+}
+
+fn is_list_item(line: &str) -> bool {
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    let rest = &line[digits..];
+    if digits > 0 {
+        rest.starts_with(". ") || rest.starts_with(") ")
+    } else {
+        rest.starts_with("- ") || rest.starts_with("* ") || rest.starts_with("+ ")
+    }
+}
+
+/// Chunks are compiled with the shared definitions, so the hash covers them
+/// as well.
+fn hash_chunk(chunk: &str, definitions: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    chunk.hash(&mut hasher);
+    definitions.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Clone)]
+struct Chunk {
+    hash: u64,
+    blocks: Vec<Block>,
+    inputs: Vec<Input>,
+}
+
+/// A compiled document kept as compiled chunks, so that only the chunks
+/// which changed are recompiled on an edit.
+#[derive(Default)]
+pub struct IncrementalDoc {
+    chunks: Vec<Chunk>,
+}
+
+impl IncrementalDoc {
+    pub fn doc(&self) -> Doc {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.blocks.iter().cloned())
+            .collect()
+    }
+    pub fn inputs(&self) -> Vec<Input> {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.inputs.iter().cloned())
+            .collect()
+    }
+    /// Forgets all compiled chunks, forcing the next update to compile
+    /// everything.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+    /// Compiles the chunks of `src` which are not already compiled.
+    pub fn update(&mut self, src: &str, ctx: &CompileContext) -> io::Result<()> {
+        let sources = split_chunks(src);
+        let hashes: Vec<u64> = sources
+            .iter()
+            .map(|chunk| hash_chunk(chunk, ctx.definitions))
+            .collect();
+
+        let compiled: HashMap<u64, &Chunk> = self
+            .chunks
+            .iter()
+            .map(|chunk| (chunk.hash, chunk))
+            .collect();
+
+        let missing: Vec<usize> = (0..sources.len())
+            .filter(|i| !compiled.contains_key(&hashes[*i]))
+            .collect();
+        let missing_sources: Vec<&str> = missing.iter().map(|i| sources[*i].as_str()).collect();
+        let mut parsed = parse_chunks(&missing_sources, ctx)?.into_iter();
+
+        let mut chunks = Vec::with_capacity(sources.len());
+        for hash in &hashes {
+            if let Some(chunk) = compiled.get(hash) {
+                chunks.push((*chunk).clone());
+                continue;
+            }
+            let blocks = parsed.next().unwrap_or_default();
+            let transformed = crate::doc::transform(Pandoc(Meta(HashMap::new()), blocks), ctx);
+            chunks.push(Chunk {
+                hash: *hash,
+                blocks: transformed.pandoc.1,
+                inputs: transformed.inputs,
+            });
+        }
+
+        self.chunks = chunks;
+        Ok(())
+    }
+}
+
+/// Parses all chunks in one go, as spawning pandoc once per chunk is slow.
+fn parse_chunks(sources: &[&str], ctx: &CompileContext) -> io::Result<Vec<Vec<Block>>> {
+    if sources.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut joined = sources.join(&format!("\n\n{}\n\n", CHUNK_SEPARATOR));
+    // Definitions produce no blocks, so they can go after the last chunk
+    joined.push_str("\n\n");
+    joined.push_str(ctx.definitions);
+    let parsed = crate::doc::parse(&joined, ctx)?;
+
+    let mut chunks = vec![vec![]];
+    for block in parsed.1 {
+        match &block {
+            Block::RawBlock(_, raw) if raw.trim() == CHUNK_SEPARATOR => chunks.push(vec![]),
+            _ => chunks.last_mut().unwrap().push(block),
+        }
+    }
+
+    if chunks.len() == sources.len() {
+        Ok(chunks)
+    } else {
+        // The separator got swallowed by a chunk, so parse them one at a time
+        sources
+            .iter()
+            .map(|src| {
+                let src = format!("{}\n\n{}", src, ctx.definitions);
+                crate::doc::parse(&src, ctx).map(|parsed| parsed.1)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(src: &str) -> Vec<String> {
+        split_chunks(src)
+            .into_iter()
+            .map(|chunk| chunk.trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn paragraphs() {
+        assert_eq!(chunks("a\nb\n\n\nc\n"), ["a\nb", "c"]);
+        assert!(chunks("").is_empty());
+    }
+
+    #[test]
+    fn lists_and_indented_lines() {
+        assert_eq!(
+            chunks("- a\n\n- b\n\n    more b\n\nafter"),
+            ["- a\n\n- b\n\n    more b", "after"]
+        );
+    }
+
+    #[test]
+    fn code_fences() {
+        assert_eq!(
+            chunks("````\ncode\n```\n\nmore\n````\n\nafter"),
+            ["````\ncode\n```\n\nmore\n````", "after"]
+        );
+        assert_eq!(
+            chunks("~~~~\ncode\n\n~~~~\n\nafter"),
+            ["~~~~\ncode\n\n~~~~", "after"]
+        );
+        // Closing fences have nothing after them
+        assert_eq!(
+            chunks("```\na\n\n``` x\n\n```\n\nafter"),
+            ["```\na\n\n``` x\n\n```", "after"]
+        );
+        assert_eq!(
+            chunks("```\na\n\n~~~\n\n```\n\nafter"),
+            ["```\na\n\n~~~\n\n```", "after"]
+        );
+    }
+
+    #[test]
+    fn fenced_divs() {
+        assert_eq!(
+            chunks("::: note\na\n\n::: inner\nb\n\n:::\n\nc\n:::\n\nafter"),
+            ["::: note\na\n\n::: inner\nb\n\n:::\n\nc\n:::", "after"]
+        );
+    }
+
+    #[test]
+    fn display_math() {
+        assert_eq!(
+            chunks("$$\na\n\nb\n$$\n\n$$c$$\n\nafter"),
+            ["$$\na\n\nb\n$$", "$$c$$", "after"]
+        );
+    }
+
+    #[test]
+    fn metadata() {
+        assert_eq!(
+            chunks("---\ntitle: a\n\nabstract: b\n---\n\nafter"),
+            ["---\ntitle: a\n\nabstract: b\n---", "after"]
+        );
+        // A rule followed by a blank line is not metadata
+        assert_eq!(chunks("---\n\na\n\nb"), ["---", "a", "b"]);
+    }
+
+    #[test]
+    fn single_chunk() {
+        for src in &[
+            "see [a]\n\n[a]: https://example.com",
+            "Term\n\n: definition\n\nafter",
+            "<div>\n\na\n\n</div>",
+            "<!--\n\na\n\n-->",
+        ] {
+            assert_eq!(split_chunks(src), [src.to_string()]);
+        }
+    }
+}
@@ -2,28 +2,63 @@
 
 use actix::*;
 use actix_files as fs;
-use actix_web::{get, web, App, Error, HttpRequest, HttpServer, Responder};
+use actix_multipart::{Field, Multipart};
+use actix_web::error::{BlockingError, ErrorNotFound, ErrorPayloadTooLarge};
+use actix_web::{get, web, App, Error, HttpRequest, HttpResponse, HttpServer, Responder};
 use actix_web_actors::ws;
-use futures::future::{ok, Future};
+use futures::future::{ok, Either, Future};
+use futures::Stream;
 use listenfd::ListenFd;
 
-use std::path::PathBuf;
+use serde::Deserialize;
+
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 mod c2s;
 mod client;
 mod s2c;
 
+mod archive;
+mod cache;
+mod citations;
+mod crossref;
+mod diagnostic;
+mod diff;
 mod doc;
+mod edit_history;
+mod export;
+mod history;
 mod hub;
+mod include;
+mod incremental;
+mod markdown;
+mod outline;
 mod project;
 mod project_actor;
+mod snapshot;
+mod variables;
 mod walk_pandoc;
+mod watch;
 
 use crate::client::{Client, ClientId};
+use crate::export::{ExportFormat, ExportOptions, JobId};
 use crate::hub::Hub;
 use crate::project::file::FileId;
 use crate::project::ProjectId;
+use crate::project_actor::{
+    AddAsset, GetArchive, GetAsset, GetExportJob, GetFileData, PrepareExport, ResolveAsset,
+    UploadFiles,
+};
+
+/// The largest asset which can be uploaded, in bytes.
+const MAX_ASSET_SIZE: usize = 32 * 1024 * 1024;
+/// The most which can be uploaded at once, over all files, in bytes.
+const MAX_UPLOAD_SIZE: usize = 128 * 1024 * 1024;
+/// The largest project archive which can be imported, in bytes.
+const MAX_IMPORT_SIZE: usize = 256 * 1024 * 1024;
 
 #[get("/ws/")]
 fn start_websocket(
@@ -55,26 +90,378 @@ fn compile_artifact(
 
     println!("{:?}", req);
 
+    let hub = hub.get_ref().clone();
     Box::new(
         hub.send(req)
             .map_err::<_, Error>(|e| {
                 println!("err");
                 e.into()
             })
-            .then(move |res| {
-                println!("ehh");
-                let p = res?.path.join(rest);
-                println!("Reading file at {:?}", p);
-                fs::NamedFile::open(p).map_err(|e| e.into())
+            .and_then(move |dir| {
+                let p = dir.path.join(&rest);
+                if p.exists() {
+                    return Either::A(ok(Some(p)));
+                }
+                // Not produced by compiling, so it might be an asset next to
+                // the file
+                Either::B(
+                    hub.send(hub::GetProject { id: project_id })
+                        .from_err()
+                        .and_then(move |project| match project {
+                            Some(project) => Either::A(
+                                project
+                                    .send(ResolveAsset {
+                                        file_id,
+                                        path: rest,
+                                    })
+                                    .from_err(),
+                            ),
+                            None => Either::B(ok(None)),
+                        }),
+                )
+            })
+            .and_then(|path| match path {
+                Some(p) => {
+                    println!("Reading file at {:?}", p);
+                    fs::NamedFile::open(p).map_err(|e| e.into())
+                }
+                None => Err(ErrorNotFound("no such artifact")),
+            }),
+    )
+}
+
+// #[get("/assets/{project_id}/{path:.*}")]
+fn get_asset(
+    info: web::Path<(u64, PathBuf)>,
+    hub: web::Data<Addr<Hub>>,
+) -> Box<dyn Future<Item = fs::NamedFile, Error = Error>> {
+    let (project_id, path) = info.into_inner();
+    let project_id = ProjectId { project_id };
+
+    Box::new(
+        hub.send(hub::GetProject { id: project_id })
+            .from_err()
+            .and_then(move |project| match project {
+                Some(project) => Either::A(project.send(GetAsset { path }).from_err()),
+                None => Either::B(ok(None)),
+            })
+            .and_then(|path| match path {
+                Some(path) => fs::NamedFile::open(path).map_err(|e| e.into()),
+                None => Err(ErrorNotFound("no such asset")),
+            }),
+    )
+}
+
+// #[post("/assets/{project_id}/{path:.*}")]
+fn upload_asset(
+    info: web::Path<(u64, PathBuf)>,
+    body: web::Bytes,
+    hub: web::Data<Addr<Hub>>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let (project_id, path) = info.into_inner();
+    let project_id = ProjectId { project_id };
+    let add = AddAsset {
+        path,
+        data: body.to_vec(),
+    };
+
+    Box::new(
+        hub.send(hub::GetProject { id: project_id })
+            .from_err()
+            .and_then(move |project| match project {
+                Some(project) => Either::A(project.send(add).from_err().map(|res| {
+                    match res {
+                        Ok(()) => HttpResponse::Created().finish(),
+                        Err(e) => HttpResponse::BadRequest()
+                            .content_type("text/plain")
+                            .body(e),
+                    }
+                })),
+                None => Either::B(ok(HttpResponse::NotFound().finish())),
             }),
     )
 }
+
+// #[get("/export/{project_id}.{ext}")]
+fn export_project(
+    info: web::Path<(u64, String)>,
+    options: web::Query<ExportOptions>,
+    hub: web::Data<Addr<Hub>>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let (project_id, ext) = info.into_inner();
+    let project_id = ProjectId { project_id };
+    let format = match ExportFormat::from_extension(&ext) {
+        Some(format) => format,
+        None => return Box::new(ok(HttpResponse::NotFound().finish())),
+    };
+    let prepare = PrepareExport {
+        format,
+        options: options.into_inner(),
+    };
+
+    Box::new(
+        hub.send(hub::GetProject { id: project_id })
+            .from_err()
+            .and_then(move |project| match project {
+                Some(project) => {
+                    Either::A(project.send(prepare).from_err().and_then(move |prepared| {
+                        // Render on the thread pool, so the project stays
+                        // responsive while pandoc runs
+                        web::block(move || {
+                            let (input, output_path) = prepared?;
+                            let data = input
+                                .run(&output_path)
+                                .and_then(|()| std::fs::read(&output_path))
+                                .map_err(|e| e.to_string());
+                            export::remove_output(&output_path);
+                            data
+                        })
+                        .then(move |res| {
+                            Ok::<_, Error>(match res {
+                                Ok(data) => HttpResponse::Ok()
+                                    .content_type(format.content_type())
+                                    .body(data),
+                                Err(BlockingError::Error(e)) => HttpResponse::InternalServerError()
+                                    .content_type("text/plain")
+                                    .body(e),
+                                Err(BlockingError::Canceled) => {
+                                    HttpResponse::InternalServerError().finish()
+                                }
+                            })
+                        })
+                    }))
+                }
+                None => Either::B(ok(HttpResponse::NotFound().finish())),
+            }),
+    )
+}
+
+// #[get("/jobs/{project_id}/{job_id}")]
+fn export_job(
+    info: web::Path<(u64, u64)>,
+    hub: web::Data<Addr<Hub>>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let (project_id, job_id) = info.into_inner();
+    let project_id = ProjectId { project_id };
+    let job = JobId { job_id };
+
+    Box::new(
+        hub.send(hub::GetProject { id: project_id })
+            .from_err()
+            .and_then(move |project| match project {
+                Some(project) => {
+                    Either::A(project.send(GetExportJob { job }).from_err().map(|output| {
+                        // Jobs which are still running or failed have no output
+                        let output = output.and_then(|(path, format)| {
+                            std::fs::read(path).ok().map(|data| (data, format))
+                        });
+                        match output {
+                            Some((data, format)) => HttpResponse::Ok()
+                                .content_type(format.content_type())
+                                .body(data),
+                            None => HttpResponse::NotFound().finish(),
+                        }
+                    }))
+                }
+                None => Either::B(ok(HttpResponse::NotFound().finish())),
+            }),
+    )
+}
+
+#[derive(Deserialize)]
+struct UploadQuery {
+    /// The folder to upload into, if not the project directory.
+    #[serde(default)]
+    folder: Option<PathBuf>,
+}
+
+// #[post("/upload/{project_id}")]
+fn upload_files(
+    info: web::Path<u64>,
+    query: web::Query<UploadQuery>,
+    multipart: Multipart,
+    hub: web::Data<Addr<Hub>>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let project_id = ProjectId {
+        project_id: info.into_inner(),
+    };
+    let folder = query.into_inner().folder;
+    let hub = hub.get_ref().clone();
+    // Fields are read one after another, so the total can be shared
+    let total = Rc::new(Cell::new(0));
+
+    Box::new(
+        multipart
+            .from_err::<Error>()
+            .and_then(move |field: Field| {
+                let name = field
+                    .content_disposition()
+                    .and_then(|cd| cd.get_filename().map(|name| name.to_string()));
+                let total = total.clone();
+                // Sizes are checked while reading, so a large upload is
+                // rejected before it is held in memory
+                field
+                    .from_err::<Error>()
+                    .fold(vec![], move |mut data, chunk| {
+                        total.set(total.get() + chunk.len());
+                        if data.len() + chunk.len() > MAX_ASSET_SIZE
+                            || total.get() > MAX_UPLOAD_SIZE
+                        {
+                            return Err(ErrorPayloadTooLarge("upload too large"));
+                        }
+                        data.extend_from_slice(&chunk);
+                        Ok(data)
+                    })
+                    .map(move |data| (name, data))
+            })
+            // Fields without a file name are form values, not files
+            .filter_map(|(name, data)| name.map(|name| (name, data)))
+            .collect()
+            .and_then(move |files| {
+                hub.send(hub::GetProject { id: project_id })
+                    .from_err()
+                    .and_then(move |project| match project {
+                        Some(project) => Either::A(
+                            project
+                                .send(UploadFiles { folder, files })
+                                .from_err()
+                                .map(|res| match res {
+                                    Ok(count) => HttpResponse::Ok().json(count),
+                                    Err(e) => HttpResponse::BadRequest()
+                                        .content_type("text/plain")
+                                        .body(e),
+                                }),
+                        ),
+                        None => Either::B(ok(HttpResponse::NotFound().finish())),
+                    })
+            }),
+    )
+}
+
+#[derive(Deserialize)]
+struct ImportQuery {
+    name: String,
+}
+
+// #[post("/import")]
+fn import_project(
+    query: web::Query<ImportQuery>,
+    body: web::Bytes,
+    hub: web::Data<Addr<Hub>>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let import = hub::ImportArchive {
+        name: query.into_inner().name,
+        data: body.to_vec(),
+    };
+
+    Box::new(hub.send(import).from_err().map(|res| {
+        match res {
+            Ok(id) => HttpResponse::Created().json(id),
+            Err(e) => HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(e),
+        }
+    }))
+}
+
+/// A `Content-Disposition` making browsers save the response as a file.
+fn attachment(file_name: &str) -> String {
+    format!("attachment; filename=\"{}\"", file_name.replace('"', ""))
+}
+
+// #[get("/download/{project_id}/{path:.*}")]
+fn download_file(
+    info: web::Path<(u64, PathBuf)>,
+    hub: web::Data<Addr<Hub>>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let (project_id, path) = info.into_inner();
+    let project_id = ProjectId { project_id };
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let content_type = if path.extension().map_or(false, |ext| ext == "md") {
+        "text/markdown; charset=utf-8"
+    } else {
+        "application/octet-stream"
+    };
+
+    Box::new(
+        hub.send(hub::GetProject { id: project_id })
+            .from_err()
+            .and_then(move |project| match project {
+                Some(project) => Either::A(project.send(GetFileData { path }).from_err().map(
+                    move |data| {
+                        match data {
+                            Some(data) => HttpResponse::Ok()
+                                .content_type(content_type)
+                                .header("Content-Disposition", attachment(&file_name))
+                                .body(data),
+                            None => HttpResponse::NotFound().finish(),
+                        }
+                    },
+                )),
+                None => Either::B(ok(HttpResponse::NotFound().finish())),
+            }),
+    )
+}
+
+// #[get("/download/{project_id}.zip")]
+fn download_project(
+    info: web::Path<u64>,
+    hub: web::Data<Addr<Hub>>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let project_id = ProjectId {
+        project_id: info.into_inner(),
+    };
+
+    Box::new(
+        hub.send(hub::GetProject { id: project_id })
+            .from_err()
+            .and_then(move |project| match project {
+                Some(project) => Either::A(project.send(GetArchive).from_err().and_then(
+                    move |project_archive| {
+                        let name = project_archive.name;
+                        // Extracting the archive gives a folder named after
+                        // the project
+                        let entries = project_archive
+                            .entries
+                            .into_iter()
+                            .map(|(path, entry)| (Path::new(&name).join(path), entry))
+                            .collect();
+                        web::block(move || archive::zip(entries)).then(move |res| {
+                            Ok::<_, Error>(match res {
+                                Ok(data) => HttpResponse::Ok()
+                                    .content_type("application/zip")
+                                    .header(
+                                        "Content-Disposition",
+                                        attachment(&format!("{}.zip", name)),
+                                    )
+                                    .body(data),
+                                Err(BlockingError::Error(e)) => HttpResponse::InternalServerError()
+                                    .content_type("text/plain")
+                                    .body(e.to_string()),
+                                Err(BlockingError::Canceled) => {
+                                    HttpResponse::InternalServerError().finish()
+                                }
+                            })
+                        })
+                    },
+                )),
+                None => Either::B(ok(HttpResponse::NotFound().finish())),
+            }),
+    )
+}
+
 fn main() {
     let mut listenfd = ListenFd::from_env();
 
     let sys = System::new("my-system");
 
-    let hub = Hub::new(PathBuf::from("./tmp")).unwrap().start();
+    let import_root = std::env::var_os("RIPORT_IMPORT_ROOT").map(PathBuf::from);
+    let hub = Hub::new(PathBuf::from("./tmp"), import_root)
+        .unwrap()
+        .start();
 
     let server = HttpServer::new(move || {
         App::new()
@@ -85,6 +472,28 @@ fn main() {
                 "/artifacts/{project_id}/{file_id}/{rest:.*}",
                 web::to_async(compile_artifact),
             )
+            .service(
+                web::resource("/assets/{project_id}/{path:.*}")
+                    .data(web::PayloadConfig::new(MAX_ASSET_SIZE))
+                    .route(web::get().to_async(get_asset))
+                    .route(web::post().to_async(upload_asset)),
+            )
+            .route("/upload/{project_id}", web::post().to_async(upload_files))
+            .service(
+                web::resource("/import")
+                    .data(web::PayloadConfig::new(MAX_IMPORT_SIZE))
+                    .route(web::post().to_async(import_project)),
+            )
+            .route(
+                "/download/{project_id}.zip",
+                web::get().to_async(download_project),
+            )
+            .route(
+                "/download/{project_id}/{path:.*}",
+                web::get().to_async(download_file),
+            )
+            .route("/export/{project_id}.{ext}", web::to_async(export_project))
+            .route("/jobs/{project_id}/{job_id}", web::to_async(export_job))
             .service(fs::Files::new("/", "./frontend/dist"))
     });
 
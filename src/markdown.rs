@@ -0,0 +1,504 @@
+use pandoc_types::definition::{
+    Alignment, Attr, Block, Format, Inline, ListAttributes, ListNumberDelim, ListNumberStyle,
+    Target,
+};
+use pulldown_cmark::{self as cmark, CodeBlockKind, Event, Options, Parser, Tag};
+
+use std::collections::HashMap;
+
+/// Parses CommonMark (with tables, footnotes, strikethrough and task lists)
+/// into pandoc blocks, without leaving the process.
+///
+/// Pandoc specific syntax such as math, citations and raw attributes is not
+/// understood and comes through as text.
+pub fn parse(src: &str) -> Vec<Block> {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+
+    let mut converter = Converter {
+        events: Parser::new_ext(src, options),
+        notes: HashMap::new(),
+    };
+    let blocks = converter.blocks();
+    let notes = converter.notes;
+
+    blocks
+        .into_iter()
+        .map(|block| resolve_notes(&notes, block))
+        .collect()
+}
+
+struct Converter<'a> {
+    events: Parser<'a>,
+    notes: HashMap<String, Vec<Block>>,
+}
+
+impl<'a> Converter<'a> {
+    /// Converts events into blocks until the end of the enclosing element.
+    /// Inlines appearing directly at block level, as they do in tight lists,
+    /// are wrapped in `Plain`.
+    fn blocks(&mut self) -> Vec<Block> {
+        let mut blocks = vec![];
+        let mut plain = vec![];
+
+        while let Some(event) = self.events.next() {
+            let block = match event {
+                Event::End(_) => break,
+                Event::Rule => Block::HorizontalRule,
+                Event::Html(html) => Block::RawBlock(Format("html".to_string()), html.to_string()),
+                Event::Start(tag) if is_block(&tag) => match self.block(tag) {
+                    Some(block) => block,
+                    None => continue,
+                },
+                event => {
+                    self.inline(event, &mut plain);
+                    continue;
+                }
+            };
+            if !plain.is_empty() {
+                blocks.push(Block::Plain(std::mem::replace(&mut plain, vec![])));
+            }
+            blocks.push(block);
+        }
+        if !plain.is_empty() {
+            blocks.push(Block::Plain(plain));
+        }
+
+        blocks
+    }
+    fn block(&mut self, tag: Tag<'a>) -> Option<Block> {
+        let block = match tag {
+            Tag::Paragraph => Block::Para(self.inlines()),
+            Tag::Heading(level) => {
+                let inlines = self.inlines();
+                let id = auto_identifier(&inlines);
+                Block::Header(level as _, Attr(id, vec![], vec![]), inlines)
+            }
+            Tag::BlockQuote => Block::BlockQuote(self.blocks()),
+            Tag::CodeBlock(kind) => {
+                let attr = match kind {
+                    CodeBlockKind::Fenced(info) => parse_info_string(&info),
+                    CodeBlockKind::Indented => Attr::null(),
+                };
+                let mut src = String::new();
+                while let Some(event) = self.events.next() {
+                    match event {
+                        Event::Text(text) => src.push_str(&text),
+                        Event::End(_) => break,
+                        _ => {}
+                    }
+                }
+                if src.ends_with('\n') {
+                    src.pop();
+                }
+                Block::CodeBlock(attr, src)
+            }
+            Tag::List(start) => {
+                let items = self.items();
+                match start {
+                    Some(start) => Block::OrderedList(
+                        ListAttributes(
+                            start as _,
+                            ListNumberStyle::Decimal,
+                            ListNumberDelim::Period,
+                        ),
+                        items,
+                    ),
+                    None => Block::BulletList(items),
+                }
+            }
+            Tag::FootnoteDefinition(label) => {
+                let blocks = self.blocks();
+                self.notes.insert(label.to_string(), blocks);
+                return None;
+            }
+            Tag::Table(alignments) => {
+                let alignments: Vec<Alignment> = alignments
+                    .into_iter()
+                    .map(|alignment| match alignment {
+                        cmark::Alignment::None => Alignment::AlignDefault,
+                        cmark::Alignment::Left => Alignment::AlignLeft,
+                        cmark::Alignment::Center => Alignment::AlignCenter,
+                        cmark::Alignment::Right => Alignment::AlignRight,
+                    })
+                    .collect();
+                let widths = vec![0.0; alignments.len()];
+                let mut head = vec![];
+                let mut rows = vec![];
+                while let Some(event) = self.events.next() {
+                    match event {
+                        Event::Start(Tag::TableHead) => head = self.cells(),
+                        Event::Start(Tag::TableRow) => rows.push(self.cells()),
+                        Event::End(_) => break,
+                        _ => {}
+                    }
+                }
+                Block::Table(vec![], alignments, widths, head, rows)
+            }
+            tag => unreachable!("{:?} is not a block", tag),
+        };
+        Some(block)
+    }
+    fn items(&mut self) -> Vec<Vec<Block>> {
+        let mut items = vec![];
+        while let Some(event) = self.events.next() {
+            match event {
+                Event::Start(Tag::Item) => items.push(self.blocks()),
+                Event::End(_) => break,
+                _ => {}
+            }
+        }
+        items
+    }
+    fn cells(&mut self) -> Vec<Vec<Block>> {
+        let mut cells = vec![];
+        while let Some(event) = self.events.next() {
+            match event {
+                Event::Start(Tag::TableCell) => {
+                    let inlines = self.inlines();
+                    cells.push(if inlines.is_empty() {
+                        vec![]
+                    } else {
+                        vec![Block::Plain(inlines)]
+                    });
+                }
+                Event::End(_) => break,
+                _ => {}
+            }
+        }
+        cells
+    }
+    /// Converts events into inlines until the end of the enclosing element.
+    fn inlines(&mut self) -> Vec<Inline> {
+        let mut inlines = vec![];
+        while let Some(event) = self.events.next() {
+            if let Event::End(_) = event {
+                break;
+            }
+            self.inline(event, &mut inlines);
+        }
+        inlines
+    }
+    fn inline(&mut self, event: Event<'a>, inlines: &mut Vec<Inline>) {
+        let inline = match event {
+            Event::Text(text) => return push_text(inlines, &text),
+            Event::Code(code) => Inline::Code(Attr::null(), code.to_string()),
+            Event::Html(html) => Inline::RawInline(Format("html".to_string()), html.to_string()),
+            Event::FootnoteReference(label) => {
+                // Definitions may come after the reference, so the contents
+                // are filled in by `resolve_notes`
+                Inline::Note(vec![
+                    Block::Null,
+                    Block::Plain(vec![Inline::Str(label.to_string())]),
+                ])
+            }
+            Event::SoftBreak => Inline::SoftBreak,
+            Event::HardBreak => Inline::LineBreak,
+            Event::TaskListMarker(checked) => {
+                inlines.push(Inline::Str(if checked { "☒" } else { "☐" }.to_string()));
+                Inline::Space
+            }
+            Event::Start(Tag::Emphasis) => Inline::Emph(self.inlines()),
+            Event::Start(Tag::Strong) => Inline::Strong(self.inlines()),
+            Event::Start(Tag::Strikethrough) => Inline::Strikeout(self.inlines()),
+            Event::Start(Tag::Link(_, url, title)) => Inline::Link(
+                Attr::null(),
+                self.inlines(),
+                Target(url.to_string(), title.to_string()),
+            ),
+            Event::Start(Tag::Image(_, url, title)) => Inline::Image(
+                Attr::null(),
+                self.inlines(),
+                Target(url.to_string(), title.to_string()),
+            ),
+            // Block level events never reach here
+            _ => return,
+        };
+        inlines.push(inline);
+    }
+}
+
+fn is_block(tag: &Tag) -> bool {
+    match tag {
+        Tag::Paragraph
+        | Tag::Heading(_)
+        | Tag::BlockQuote
+        | Tag::CodeBlock(_)
+        | Tag::List(_)
+        | Tag::FootnoteDefinition(_)
+        | Tag::Table(_) => true,
+        _ => false,
+    }
+}
+
+/// Splits text into words the way pandoc does.
+fn push_text(inlines: &mut Vec<Inline>, text: &str) {
+    for (i, word) in text.split(' ').enumerate() {
+        if i > 0 {
+            match inlines.last() {
+                Some(Inline::Space) => {}
+                _ => inlines.push(Inline::Space),
+            }
+        }
+        if word.is_empty() {
+            continue;
+        }
+        // Text is sometimes split into several events, so join up words
+        if let Some(Inline::Str(last)) = inlines.last_mut() {
+            last.push_str(word);
+        } else {
+            inlines.push(Inline::Str(word.to_string()));
+        }
+    }
+}
+
+/// Parses fence info strings, both plain (`python`) and pandoc style
+/// (`{.python #id key="value"}`).
+fn parse_info_string(info: &str) -> Attr {
+    let info = info.trim();
+    if !(info.starts_with('{') && info.ends_with('}')) {
+        let classes = info
+            .split_whitespace()
+            .next()
+            .map(|lang| vec![lang.to_string()])
+            .unwrap_or_default();
+        return Attr("".to_string(), classes, vec![]);
+    }
+
+    let mut attr = Attr::null();
+    let mut rest = &info[1..info.len() - 1];
+    loop {
+        rest = rest.trim_start();
+        let end = match (rest.find("=\""), rest.find(' ')) {
+            // Quoted values may contain spaces, so read up to the closing quote
+            (Some(eq), space) if space.map_or(true, |space| eq < space) => rest[eq + 2..]
+                .find('"')
+                .map(|i| eq + 2 + i + 1)
+                .unwrap_or_else(|| rest.len()),
+            (_, Some(space)) => space,
+            _ => rest.len(),
+        };
+        if end == 0 {
+            break;
+        }
+        let token = &rest[..end];
+        if token.starts_with('.') {
+            attr.1.push(token[1..].to_string());
+        } else if token.starts_with('#') {
+            attr.0 = token[1..].to_string();
+        } else if let Some(eq) = token.find('=') {
+            let value = token[eq + 1..].trim_matches('"');
+            attr.2.push((token[..eq].to_string(), value.to_string()));
+        } else {
+            attr.1.push(token.to_string());
+        }
+        rest = &rest[end..];
+    }
+    attr
+}
+
+/// Generates a heading identifier like pandoc's `auto_identifiers` extension.
+/// Letters are lowercased as in Unicode, so ids keep letters like `é` as
+/// pandoc does.
+fn auto_identifier(inlines: &[Inline]) -> String {
+    let text: String = crate::walk_pandoc::stringify(inlines)
+        .chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace() || "_-.".contains(*c))
+        .flat_map(char::to_lowercase)
+        .collect();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let id: String = words
+        .join("-")
+        .chars()
+        .skip_while(|c| !c.is_alphabetic())
+        .collect();
+    if id.is_empty() {
+        "section".to_string()
+    } else {
+        id
+    }
+}
+
+fn resolve_notes(notes: &HashMap<String, Vec<Block>>, block: Block) -> Block {
+    let inlines = |inlines: Vec<Inline>| -> Vec<Inline> {
+        inlines
+            .into_iter()
+            .map(|inline| resolve_note(notes, inline))
+            .collect()
+    };
+    let blocks = |blocks: Vec<Block>| -> Vec<Block> {
+        blocks
+            .into_iter()
+            .map(|block| resolve_notes(notes, block))
+            .collect()
+    };
+    match block {
+        Block::Plain(i) => Block::Plain(inlines(i)),
+        Block::Para(i) => Block::Para(inlines(i)),
+        Block::Header(level, attr, i) => Block::Header(level, attr, inlines(i)),
+        Block::BlockQuote(b) => Block::BlockQuote(blocks(b)),
+        Block::BulletList(items) => Block::BulletList(items.into_iter().map(blocks).collect()),
+        Block::OrderedList(attrs, items) => {
+            Block::OrderedList(attrs, items.into_iter().map(blocks).collect())
+        }
+        Block::Table(caption, alignments, widths, head, rows) => Block::Table(
+            caption,
+            alignments,
+            widths,
+            head.into_iter().map(blocks).collect(),
+            rows.into_iter()
+                .map(|row| row.into_iter().map(blocks).collect())
+                .collect(),
+        ),
+        block => block,
+    }
+}
+
+fn resolve_note(notes: &HashMap<String, Vec<Block>>, inline: Inline) -> Inline {
+    let inlines = |inlines: Vec<Inline>| -> Vec<Inline> {
+        inlines
+            .into_iter()
+            .map(|inline| resolve_note(notes, inline))
+            .collect()
+    };
+    match inline {
+        Inline::Note(placeholder) => match placeholder.as_slice() {
+            [Block::Null, Block::Plain(label)] => match label.as_slice() {
+                [Inline::Str(label)] => Inline::Note(notes.get(label).cloned().unwrap_or_default()),
+                _ => Inline::Note(placeholder),
+            },
+            _ => Inline::Note(placeholder),
+        },
+        Inline::Emph(i) => Inline::Emph(inlines(i)),
+        Inline::Strong(i) => Inline::Strong(inlines(i)),
+        Inline::Strikeout(i) => Inline::Strikeout(inlines(i)),
+        Inline::Link(attr, i, target) => Inline::Link(attr, inlines(i), target),
+        inline => inline,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn str(s: &str) -> Inline {
+        Inline::Str(s.to_string())
+    }
+
+    fn plain(s: &str) -> Vec<Block> {
+        vec![Block::Plain(vec![str(s)])]
+    }
+
+    // The expected blocks are what `pandoc -f markdown -t json` gives
+
+    #[test]
+    fn headings() {
+        assert_eq!(
+            parse("# Héllo *World*\n\n## 1. Intro, again!\n\n### 42"),
+            vec![
+                Block::Header(
+                    1,
+                    Attr("héllo-world".to_string(), vec![], vec![]),
+                    vec![
+                        str("Héllo"),
+                        Inline::Space,
+                        Inline::Emph(vec![str("World")])
+                    ],
+                ),
+                Block::Header(
+                    2,
+                    Attr("intro-again".to_string(), vec![], vec![]),
+                    vec![
+                        str("1."),
+                        Inline::Space,
+                        str("Intro,"),
+                        Inline::Space,
+                        str("again!")
+                    ],
+                ),
+                Block::Header(
+                    3,
+                    Attr("section".to_string(), vec![], vec![]),
+                    vec![str("42")],
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn non_ascii_ids() {
+        let id = |src: &str| match &parse(src)[0] {
+            Block::Header(_, attr, _) => attr.0.clone(),
+            block => panic!("{:?} is not a header", block),
+        };
+        assert_eq!(id("# Ärger über Öl"), "ärger-über-öl");
+        assert_eq!(id("# Ελληνικά"), "ελληνικά");
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            parse("- a\n- b\n\n---\n\n3. c\n\n4. d"),
+            vec![
+                Block::BulletList(vec![plain("a"), plain("b")]),
+                Block::HorizontalRule,
+                Block::OrderedList(
+                    ListAttributes(3, ListNumberStyle::Decimal, ListNumberDelim::Period),
+                    vec![
+                        vec![Block::Para(vec![str("c")])],
+                        vec![Block::Para(vec![str("d")])],
+                    ],
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn tables() {
+        assert_eq!(
+            parse("| a | b |\n|:--|--:|\n| 1 |   |"),
+            vec![Block::Table(
+                vec![],
+                vec![Alignment::AlignLeft, Alignment::AlignRight],
+                vec![0.0, 0.0],
+                vec![plain("a"), plain("b")],
+                vec![vec![plain("1"), vec![]]],
+            )]
+        );
+    }
+
+    #[test]
+    fn notes() {
+        assert_eq!(
+            parse("a[^n] b\n\n[^n]: The *note*."),
+            vec![Block::Para(vec![
+                str("a"),
+                Inline::Note(vec![Block::Para(vec![
+                    str("The"),
+                    Inline::Space,
+                    Inline::Emph(vec![str("note")]),
+                    str("."),
+                ])]),
+                Inline::Space,
+                str("b"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn code_block_attributes() {
+        assert_eq!(
+            parse("```{.python #plot caption=\"A plot\"}\nx = 1\n```"),
+            vec![Block::CodeBlock(
+                Attr(
+                    "plot".to_string(),
+                    vec!["python".to_string()],
+                    vec![("caption".to_string(), "A plot".to_string())],
+                ),
+                "x = 1".to_string(),
+            )]
+        );
+    }
+}
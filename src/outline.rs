@@ -0,0 +1,251 @@
+use pandoc_types::definition::{Attr, Block, Inline, Meta, Pandoc, Target};
+
+use serde::Serialize;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::crossref::is_reference;
+use crate::project::file::{Doc, FileId};
+use crate::project::metadata::inlines;
+use crate::walk_pandoc::{stringify, walk_pandoc, Walk};
+
+/// A heading of the project outline.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Heading {
+    pub file: FileId,
+    pub level: i32,
+    /// The id pandoc gave the header, used to link to it.
+    pub id: String,
+    pub title: String,
+}
+
+/// The headings of the doc of a file. Only top level headers are sections,
+/// so headers in lists or quotes are left out.
+pub fn headings(file: FileId, doc: &[Block]) -> Vec<Heading> {
+    doc.iter()
+        .filter_map(|block| match block {
+            Block::Header(level, attr, title) => Some(Heading {
+                file,
+                level: *level,
+                id: attr.0.clone(),
+                title: stringify(title),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Makes the ids of headers unique across the docs, which are parts of the
+/// same document in order. Pandoc only does so within each file, so two files
+/// starting with `# Introduction` would both have `introduction`. Later
+/// headers get a number appended, as pandoc does, and links to them from
+/// their own doc follow. Labels for cross-references are left as they are.
+pub fn unique_ids(docs: Vec<Doc>) -> Vec<Doc> {
+    let mut seen = HashSet::new();
+    docs.into_iter()
+        .map(|doc| {
+            let mut renamed = HashMap::new();
+            let doc: Doc = doc
+                .into_iter()
+                .map(|block| match block {
+                    Block::Header(level, Attr(id, classes, attrs), title) => {
+                        let id = if id.is_empty() || is_reference(&id) {
+                            id
+                        } else {
+                            let mut unique = id.clone();
+                            let mut n = 1;
+                            while seen.contains(&unique) {
+                                unique = format!("{}-{}", id, n);
+                                n += 1;
+                            }
+                            if unique != id {
+                                renamed.insert(format!("#{}", id), format!("#{}", unique));
+                            }
+                            unique
+                        };
+                        seen.insert(id.clone());
+                        Block::Header(level, Attr(id, classes, attrs), title)
+                    }
+                    block => block,
+                })
+                .collect();
+            if renamed.is_empty() {
+                return doc;
+            }
+            walk_pandoc(
+                &mut RenameLinks { renamed },
+                Pandoc(Meta(HashMap::new()), doc),
+            )
+            .1
+        })
+        .collect()
+}
+
+/// Points links to headers which were given another id to the new one.
+struct RenameLinks {
+    renamed: HashMap<String, String>,
+}
+
+impl Walk for RenameLinks {
+    fn inline(&mut self, inline: Inline) -> Vec<Inline> {
+        match inline {
+            Inline::Link(attr, inlines, Target(url, title)) => {
+                let url = self.renamed.get(&url).cloned().unwrap_or(url);
+                vec![Inline::Link(attr, inlines, Target(url, title))]
+            }
+            inline => vec![inline],
+        }
+    }
+}
+
+/// A table of contents for the outline, as nested lists linking to each
+/// heading.
+pub fn toc(outline: &[Heading]) -> Block {
+    Block::Div(
+        Attr("toc".to_string(), vec!["toc".to_string()], vec![]),
+        vec![Block::BulletList(toc_items(outline))],
+    )
+}
+
+fn toc_items(headings: &[Heading]) -> Vec<Vec<Block>> {
+    let mut items = vec![];
+    let mut i = 0;
+    while i < headings.len() {
+        let heading = &headings[i];
+        // Everything up to the next heading of the same level or above is
+        // nested under this one
+        let end = headings[i + 1..]
+            .iter()
+            .position(|h| h.level <= heading.level)
+            .map_or(headings.len(), |p| i + 1 + p);
+
+        let title = inlines(&heading.title);
+        let title = if heading.id.is_empty() {
+            title
+        } else {
+            vec![Inline::Link(
+                Attr::null(),
+                title,
+                Target(format!("#{}", heading.id), String::new()),
+            )]
+        };
+        let mut item = vec![Block::Plain(title)];
+        let children = &headings[i + 1..end];
+        if !children.is_empty() {
+            item.push(Block::BulletList(toc_items(children)));
+        }
+        items.push(item);
+        i = end;
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(level: i32, id: &str, title: &str) -> Block {
+        Block::Header(level, Attr(id.to_string(), vec![], vec![]), inlines(title))
+    }
+
+    fn link(url: &str) -> Block {
+        Block::Para(vec![Inline::Link(
+            Attr::null(),
+            inlines("see"),
+            Target(url.to_string(), String::new()),
+        )])
+    }
+
+    fn ids(docs: &[Doc]) -> Vec<Vec<String>> {
+        docs.iter()
+            .map(|doc| {
+                doc.iter()
+                    .filter_map(|block| match block {
+                        Block::Header(_, attr, _) => Some(attr.0.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ids_are_unique_across_files() {
+        let docs = unique_ids(vec![
+            vec![header(1, "introduction", "Introduction")],
+            vec![header(1, "introduction", "Introduction")],
+            vec![
+                header(1, "introduction", "Introduction"),
+                header(2, "introduction-1", "Introduction 1"),
+            ],
+        ]);
+        assert_eq!(
+            ids(&docs),
+            vec![
+                vec!["introduction"],
+                vec!["introduction-1"],
+                vec!["introduction-2", "introduction-1-1"],
+            ]
+        );
+    }
+
+    #[test]
+    fn links_follow_renamed_headers_in_their_file() {
+        let docs = unique_ids(vec![
+            vec![header(1, "results", "Results"), link("#results")],
+            vec![
+                header(1, "results", "Results"),
+                link("#results"),
+                link("#other"),
+            ],
+        ]);
+        assert_eq!(docs[0][1], link("#results"));
+        assert_eq!(docs[1][1], link("#results-1"));
+        assert_eq!(docs[1][2], link("#other"));
+    }
+
+    #[test]
+    fn labels_and_empty_ids_are_kept() {
+        let docs = unique_ids(vec![
+            vec![header(1, "sec:intro", "Intro"), header(1, "", "Intro")],
+            vec![header(1, "sec:intro", "Intro"), header(1, "", "Intro")],
+        ]);
+        assert_eq!(
+            ids(&docs),
+            vec![vec!["sec:intro", ""], vec!["sec:intro", ""]]
+        );
+    }
+
+    #[test]
+    fn toc_nests_lower_levels() {
+        let file = FileId { file_id: 0 };
+        let outline = headings(
+            file,
+            &[
+                header(1, "a", "A"),
+                header(2, "b", "B"),
+                header(1, "c", "C"),
+            ],
+        );
+        let item = |id: &str, title: &str| {
+            Block::Plain(vec![Inline::Link(
+                Attr::null(),
+                inlines(title),
+                Target(format!("#{}", id), String::new()),
+            )])
+        };
+        assert_eq!(
+            toc(&outline),
+            Block::Div(
+                Attr("toc".to_string(), vec!["toc".to_string()], vec![]),
+                vec![Block::BulletList(vec![
+                    vec![
+                        item("a", "A"),
+                        Block::BulletList(vec![vec![item("b", "B")]])
+                    ],
+                    vec![item("c", "C")],
+                ])],
+            )
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Extensions of files which are kept in a project as they are, rather than
+/// compiled.
+pub const ASSET_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "pdf", "csv"];
+
+pub fn is_asset(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| {
+            ASSET_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        })
+}
+
+/// Whether `path` stays inside the directory it is relative to, so it can
+/// safely come from a client.
+pub fn is_project_path(path: &Path) -> bool {
+    path.components().count() > 0
+        && path.components().all(|c| match c {
+            Component::Normal(_) => true,
+            _ => false,
+        })
+}
+
+/// The assets in `folder` and below, sorted by path. Hidden directories,
+/// such as the cache in `.riport`, are skipped.
+pub fn asset_files(project_dir: &Path, folder: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(project_dir.join(folder))?
+        .map(|entry| entry.map(|entry| folder.join(entry.file_name())))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+
+    let mut assets = vec![];
+    for path in entries {
+        let hidden = path
+            .file_name()
+            .map_or(false, |name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if project_dir.join(&path).is_dir() {
+            assets.extend(asset_files(project_dir, &path)?);
+        } else if is_asset(&path) {
+            assets.push(path);
+        }
+    }
+    Ok(assets)
+}
@@ -4,16 +4,44 @@ use pandoc_types::definition::Block;
 
 use serde::{Deserialize, Serialize};
 
+use std::collections::hash_map::DefaultHasher;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
+use crate::cache::CompileCache;
+use crate::citations::{self, CitationSettings};
 use crate::client::Client;
+use crate::diagnostic::Diagnostic;
+use crate::doc::{BackendKind, CompileContext, Input};
+use crate::incremental::IncrementalDoc;
 use crate::project::{Project, ProjectId};
 
 pub type Doc = Vec<Block>;
 
+/// A change to a `Doc`. Indices refer to the doc as it is after the previous
+/// edits have been applied.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum DocEdit {
+    Insert {
+        index: usize,
+        blocks: Vec<Block>,
+    },
+    Remove {
+        index: usize,
+        count: usize,
+    },
+    Replace {
+        index: usize,
+        count: usize,
+        blocks: Vec<Block>,
+    },
+}
+
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct FileId {
     pub file_id: u64,
@@ -23,51 +51,199 @@ pub struct File {
     pub id: FileId,
     pub project_id: ProjectId,
     pub name: String,
+    /// Where the source is stored, relative to the project directory.
+    pub path: PathBuf,
     pub src: String,
+    /// The source with includes expanded, which is what gets compiled.
+    pub expanded: String,
+    /// The doc as compiled from this file alone.
+    pub local_doc: Doc,
+    /// The doc after linking it with the rest of the project, which is what
+    /// is shown and exported.
     pub doc: Option<Doc>,
+    pub compiled: IncrementalDoc,
     pub tmpdir: PathBuf,
+    pub project_dir: PathBuf,
+    pub cache: Arc<Mutex<CompileCache>>,
+    pub backend: BackendKind,
+    pub citations: CitationSettings,
+    /// Link reference definitions of the whole project.
+    pub definitions: String,
+    /// Includes which could not be expanded.
+    pub include_diagnostics: Vec<Diagnostic>,
+    /// Problems found while compiling the file by itself.
+    pub compile_diagnostics: Vec<Diagnostic>,
+    /// Problems found when linking the file with the rest of the project.
+    pub link_diagnostics: Vec<Diagnostic>,
+    /// The diagnostics as they were when last taken.
+    sent_diagnostics: Vec<Diagnostic>,
+    /// Project files read while compiling, which makes the doc stale when
+    /// they change.
+    pub inputs: Vec<Input>,
+    /// A hash of the source as the server last read it from or wrote it to
+    /// disk, so its own writes are not taken for changes made outside of it.
+    disk_hash: u64,
+}
+
+fn hash_src(src: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    src.hash(&mut hasher);
+    hasher.finish()
 }
 
 impl File {
     pub fn new(
         id: FileId,
         project_id: ProjectId,
-        name: String,
+        path: PathBuf,
         src: String,
         tmpdir: PathBuf,
+        project_dir: PathBuf,
+        cache: Arc<Mutex<CompileCache>>,
+        backend: BackendKind,
+        citations: CitationSettings,
+        definitions: String,
     ) -> File {
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        // Files are created from what is on disk, or written right after
+        let disk_hash = hash_src(&src);
         let mut file = File {
             id,
             project_id,
             name,
+            path,
+            expanded: src.clone(),
             src,
+            local_doc: vec![],
             doc: None,
+            compiled: IncrementalDoc::default(),
             tmpdir,
+            project_dir,
+            cache,
+            backend,
+            citations,
+            definitions,
+            include_diagnostics: vec![],
+            compile_diagnostics: vec![],
+            link_diagnostics: vec![],
+            sent_diagnostics: vec![],
+            inputs: vec![],
+            disk_hash,
         };
         file.compile();
         file
     }
+    /// Recompiles the parts of the source which changed. The doc is updated
+    /// once the project links it.
     pub fn compile(&mut self) {
-        let doc = crate::doc::compile(&self.src, &self.tmpdir).expect("failed to compile");
-        self.doc = Some(doc.1);
+        let ctx = CompileContext {
+            run_dir: &self.tmpdir,
+            project_dir: &self.project_dir,
+            cache: &self.cache,
+            backend: self.backend,
+            definitions: &self.definitions,
+        };
+        self.compiled
+            .update(&self.expanded, &ctx)
+            .expect("failed to compile");
+        let doc = self.compiled.doc();
+        self.check_citations(&doc);
+        self.local_doc = doc;
+        self.inputs = self.compiled.inputs();
+    }
+    /// Replaces the doc, returning the edits made to it.
+    pub fn set_doc(&mut self, doc: Doc) -> Vec<DocEdit> {
+        let edits = crate::diff::doc_edits(self.doc.as_ref().map_or(&[][..], |doc| &doc[..]), &doc);
+        self.doc = Some(doc);
+        edits
+    }
+    /// Compiles the whole source with pandoc, whatever the backend, as pandoc
+    /// understands more markdown than the native backend used for previews.
+    pub fn compile_with_pandoc(&self) -> io::Result<Doc> {
+        let ctx = CompileContext {
+            run_dir: &self.tmpdir,
+            project_dir: &self.project_dir,
+            cache: &self.cache,
+            backend: BackendKind::Pandoc,
+            definitions: &self.definitions,
+        };
+        let src = format!("{}\n\n{}", self.expanded, self.definitions);
+        let compiled = crate::doc::compile(&src, &ctx)?;
+        Ok(compiled.pandoc.1)
+    }
+    /// Checks that the citations of a doc are all in the bibliography. They
+    /// are only formatted by pandoc on export, as running citeproc on every
+    /// edit is too slow for previews.
+    fn check_citations(&mut self, doc: &[Block]) {
+        self.compile_diagnostics = if self.citations.enabled() {
+            citations::unresolved(&self.citations, &self.project_dir, &self.src, doc)
+        } else {
+            vec![]
+        };
+    }
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.include_diagnostics
+            .iter()
+            .chain(&self.compile_diagnostics)
+            .chain(&self.link_diagnostics)
+            .cloned()
+            .collect()
     }
-    pub fn write_to_disk(&self, dir: &Path) -> io::Result<()> {
-        unimplemented!()
+    /// The diagnostics, if they changed since the last call.
+    pub fn take_new_diagnostics(&mut self) -> Option<Vec<Diagnostic>> {
+        let diagnostics = self.diagnostics();
+        if diagnostics != self.sent_diagnostics {
+            self.sent_diagnostics = diagnostics.clone();
+            Some(diagnostics)
+        } else {
+            None
+        }
+    }
+    /// Throws away everything compiled so far and compiles from scratch.
+    pub fn recompile(&mut self) {
+        self.compiled.clear();
+        self.compile()
+    }
+    pub fn inputs_changed(&self) -> bool {
+        self.inputs
+            .iter()
+            .any(|input| input.changed(&self.project_dir))
+    }
+    /// The folder the file is in, which is empty for the project directory.
+    pub fn folder(&self) -> &Path {
+        self.path.parent().unwrap_or_else(|| Path::new(""))
+    }
+    pub fn write_to_disk(&mut self, dir: &Path) -> io::Result<()> {
+        let path = dir.join(&self.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &self.src)?;
+        self.disk_hash = hash_src(&self.src);
+        Ok(())
+    }
+    /// Whether `src`, read from disk, differs from what the server last read
+    /// or wrote there. Comparing with the current source instead would take
+    /// the server's own writes for changes once the file is edited again.
+    pub fn changed_on_disk(&self, src: &str) -> bool {
+        hash_src(src) != self.disk_hash
+    }
+    /// Notes that `src` was read from disk.
+    pub fn read_from_disk(&mut self, src: &str) {
+        self.disk_hash = hash_src(src);
     }
     pub fn get_info(&self) -> FileInfo {
         FileInfo {
             name: self.name.clone(),
             id: self.id,
+            path: self.path.clone(),
         }
     }
-    pub fn update_src(&mut self, src: String) {
-        self.src = src;
-        self.compile()
-    }
 }
 
 #[derive(Serialize, Clone, MessageResponse)]
 pub struct FileInfo {
     pub name: String,
     pub id: FileId,
+    pub path: PathBuf,
 }
@@ -0,0 +1,60 @@
+use pandoc_types::definition::{Block, Inline, Meta, MetaValue};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+
+/// Document metadata of a project, which pandoc uses for the title block of
+/// exported documents.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub date: Option<String>,
+    #[serde(rename = "abstract")]
+    pub abstract_: Option<String>,
+    /// Whether a table of contents goes before the first file.
+    pub toc: bool,
+}
+
+impl Metadata {
+    pub fn to_meta(&self) -> Meta {
+        let mut meta = HashMap::new();
+        if let Some(title) = &self.title {
+            meta.insert("title".to_string(), MetaValue::MetaInlines(inlines(title)));
+        }
+        if !self.authors.is_empty() {
+            let authors = self
+                .authors
+                .iter()
+                .map(|author| MetaValue::MetaInlines(inlines(author)))
+                .collect();
+            meta.insert("author".to_string(), MetaValue::MetaList(authors));
+        }
+        if let Some(date) = &self.date {
+            meta.insert("date".to_string(), MetaValue::MetaInlines(inlines(date)));
+        }
+        if let Some(abstract_) = &self.abstract_ {
+            let paragraphs = abstract_
+                .split("\n\n")
+                .filter(|p| !p.trim().is_empty())
+                .map(|p| Block::Para(inlines(p)))
+                .collect();
+            meta.insert("abstract".to_string(), MetaValue::MetaBlocks(paragraphs));
+        }
+        Meta(meta)
+    }
+}
+
+/// Plain text as inlines, the way pandoc would read it.
+pub fn inlines(text: &str) -> Vec<Inline> {
+    let mut inlines = vec![];
+    for word in text.split_whitespace() {
+        if !inlines.is_empty() {
+            inlines.push(Inline::Space);
+        }
+        inlines.push(Inline::Str(word.to_string()));
+    }
+    inlines
+}
@@ -1,15 +1,34 @@
+pub mod asset;
 pub mod file;
+pub mod metadata;
+pub mod template;
+
+use pandoc_types::definition::{Inline, Meta, Pandoc, Target};
 
 use serde::{Deserialize, Serialize};
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
+use crate::archive;
+use crate::cache::{CompileCache, DEFAULT_CACHE_SIZE};
+use crate::citations::CitationSettings;
 use crate::client::Client;
-use crate::project::file::{File, FileId, FileInfo};
+use crate::crossref;
+use crate::diagnostic::Diagnostic;
+use crate::doc::BackendKind;
+use crate::export::{ExportFormat, ExportInput, ExportOptions, ExportSettings};
+use crate::history;
+use crate::include;
+use crate::outline::{self, Heading};
+use crate::project::file::{Doc, DocEdit, File, FileId, FileInfo};
+use crate::project::metadata::Metadata;
+use crate::variables;
+use crate::walk_pandoc::{walk_pandoc, Walk};
 
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct ProjectId {
@@ -21,6 +40,22 @@ pub struct ProjectConfig {
     pub id: ProjectId,
     pub name: String,
     pub order: Vec<PathBuf>,
+    /// Folders of the project, including those which hold no files yet.
+    #[serde(default)]
+    pub folders: Vec<PathBuf>,
+    /// Which markdown parser to use for live preview.
+    #[serde(default)]
+    pub backend: BackendKind,
+    #[serde(default)]
+    pub metadata: Metadata,
+    /// Templates and styles applied when exporting.
+    #[serde(default)]
+    pub export: ExportSettings,
+    #[serde(default)]
+    pub citations: CitationSettings,
+    /// Values substituted for `{{name}}` throughout the documents.
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
 }
 
 pub struct Project {
@@ -28,28 +63,114 @@ pub struct Project {
     pub name: String,
     pub path: PathBuf,
     pub order: Vec<FileId>,
+    /// Folders relative to the project directory, holding files or other
+    /// folders.
+    pub folders: BTreeSet<PathBuf>,
+    /// Images and other files used by the documents, relative to the
+    /// project directory.
+    pub assets: BTreeSet<PathBuf>,
     pub files: HashMap<FileId, File>,
     pub tmpdir: PathBuf,
+    pub cache: Arc<Mutex<CompileCache>>,
+    pub backend: BackendKind,
+    pub metadata: Metadata,
+    pub export_settings: ExportSettings,
+    pub citations: CitationSettings,
+    pub variables: HashMap<String, String>,
+    /// Link reference definitions collected from all files.
+    pub definitions: String,
+    /// The docs of all files in order, as one document.
+    pub doc: Doc,
+    pub sections: Vec<DocSection>,
+    /// The headings of all files in order.
+    pub outline: Vec<Heading>,
+}
+
+/// Where the blocks of a file are in the project doc.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DocSection {
+    pub file: FileId,
+    pub start: usize,
+    pub len: usize,
+}
+
+/// The edits made by linking the project.
+pub struct LinkEdits {
+    pub files: Vec<(FileId, Vec<DocEdit>)>,
+    /// Edits to the project doc.
+    pub project: Vec<DocEdit>,
+}
+
+/// What changed when a path was reloaded from disk.
+pub enum Reload {
+    Unchanged,
+    /// The source of a file changed to the one read from disk.
+    Source(FileId, String),
+    /// A file, folder or asset was added or an asset was removed.
+    Added,
+    /// Files, folders or assets were renamed. Files keep their sources until
+    /// their new paths are reloaded.
+    Moved,
+}
+
+/// What storing an uploaded file did.
+pub enum Upload {
+    /// A markdown file or an asset was added.
+    Added(Option<FileId>),
+    /// The upload is a new source for an existing file. It is left to the
+    /// caller to apply, so the replacement can be undone like an edit.
+    Replaces(FileId, String),
 }
 
 impl Project {
-    pub fn empty(id: ProjectId, name: String, path: PathBuf, tmpdir: PathBuf) -> Project {
-        Project {
+    pub fn empty(
+        id: ProjectId,
+        name: String,
+        path: PathBuf,
+        tmpdir: PathBuf,
+    ) -> io::Result<Project> {
+        let cache = CompileCache::open(path.join(".riport").join("cache"), DEFAULT_CACHE_SIZE)?;
+        Ok(Project {
             id,
             name,
             path,
             order: vec![],
+            folders: BTreeSet::new(),
+            assets: BTreeSet::new(),
             files: HashMap::new(),
             tmpdir,
-        }
+            cache: Arc::new(Mutex::new(cache)),
+            backend: BackendKind::default(),
+            metadata: Metadata::default(),
+            export_settings: ExportSettings::default(),
+            citations: CitationSettings::default(),
+            variables: HashMap::new(),
+            definitions: String::new(),
+            doc: vec![],
+            sections: vec![],
+            outline: vec![],
+        })
     }
-    pub fn new_file(&mut self, name: String, src: String) -> FileId {
+    /// Adds a file at `path`, relative to the project directory.
+    pub fn new_file(&mut self, path: PathBuf, src: String) -> FileId {
         let id = FileId {
             file_id: self.files.len() as _,
         };
         let file_tmpdir = self.tmpdir.join(&format!("{}", id.file_id));
         fs::create_dir_all(&file_tmpdir).unwrap();
-        let file = File::new(id, self.id, name, src, file_tmpdir);
+        self.add_folders(path.parent());
+        let file = File::new(
+            id,
+            self.id,
+            path,
+            src,
+            file_tmpdir,
+            self.path.clone(),
+            self.cache.clone(),
+            self.backend,
+            self.citations.clone(),
+            self.definitions.clone(),
+        );
         self.files.insert(id, file);
         self.order.push(id);
 
@@ -63,110 +184,786 @@ impl Project {
             name: self.name.clone(),
             id: self.id,
             files: self.order.clone(),
+            tree: self.file_tree(),
+            metadata: self.metadata.clone(),
+            history: history::is_enabled(&self.path),
         }
     }
     pub fn generate_config(&self) -> ProjectConfig {
-        let name = self.name.clone();
-        let id = self.id;
-        let infos = self.generate_file_info();
         ProjectConfig {
-            name,
-            id,
-            order: infos
-                .into_iter()
-                .map(|info: FileInfo| PathBuf::from(info.name))
+            name: self.name.clone(),
+            id: self.id,
+            backend: self.backend,
+            metadata: self.metadata.clone(),
+            export: self.export_settings.clone(),
+            citations: self.citations.clone(),
+            variables: self.variables.clone(),
+            folders: self.folders.iter().cloned().collect(),
+            order: self
+                .order
+                .iter()
+                .map(|id| self.files[id].path.clone())
                 .collect(),
         }
     }
-    pub fn write_to_disk(&self, dir: PathBuf) -> io::Result<()> {
-        let name = self.name.clone();
-        let id = self.id;
+    pub fn write_to_disk(&mut self, dir: PathBuf) -> io::Result<()> {
+        fs::create_dir_all(&dir)?;
 
-        fs::create_dir_all(&dir).unwrap();
-
-        let mut order = vec![];
         for id in &self.order {
-            let file = &self.files[id];
-            file.write_to_disk(&dir)?;
-            // TODO
-            // order.push(file.path.strip_prefix(&dir).unwrap().to_owned());
+            self.files.get_mut(id).unwrap().write_to_disk(&dir)?;
         }
 
-        let config = ProjectConfig { name, id, order };
-
+        self.write_config(&dir)
+    }
+    pub fn write_config(&self, dir: &Path) -> io::Result<()> {
         fs::write(
             dir.join("config.json"),
-            serde_json::to_string(&config).unwrap(),
-        )?;
-
-        Ok(())
+            serde_json::to_string_pretty(&self.generate_config()).unwrap(),
+        )
+    }
+    /// Makes a directory into the project `id`. Unless the directory already
+    /// has a config, one is written with the markdown files ordered by path,
+    /// starting with the index if there is one.
+    pub fn prepare_import(dir: &Path, id: ProjectId, name: String) -> io::Result<()> {
+        let config_path = dir.join("config.json");
+        let config = if config_path.exists() {
+            let config = fs::read_to_string(&config_path)?;
+            let mut config: ProjectConfig = serde_json::from_str(&config)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            config.id = id;
+            config.name = name;
+            config
+        } else {
+            let mut order = markdown_files(dir, Path::new(""))?;
+            let index = order.iter().position(|path| {
+                let stem = path.file_stem().unwrap().to_string_lossy().to_lowercase();
+                path.components().count() == 1 && (stem == "index" || stem == "readme")
+            });
+            if let Some(index) = index {
+                let index = order.remove(index);
+                order.insert(0, index);
+            }
+            ProjectConfig {
+                id,
+                name,
+                order,
+                folders: vec![],
+                backend: BackendKind::default(),
+                metadata: Metadata::default(),
+                export: ExportSettings::default(),
+                citations: CitationSettings::default(),
+                variables: HashMap::new(),
+            }
+        };
+        fs::write(config_path, serde_json::to_string_pretty(&config).unwrap())
     }
     pub fn read_from_disk(dir: PathBuf, tmpdir: PathBuf) -> io::Result<Project> {
         let config = fs::read_to_string(dir.join("config.json"))?;
-        let config: ProjectConfig =
-            serde_json::from_str(&config).expect("failed to parse project config");
+        let config: ProjectConfig = serde_json::from_str(&config)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
-        let mut project = Project::empty(config.id, config.name, dir, tmpdir);
+        let mut project = Project::empty(config.id, config.name, dir, tmpdir)?;
+        project.backend = config.backend;
+        project.metadata = config.metadata;
+        project.export_settings = config.export;
+        project.citations = config.citations;
+        project.variables = config.variables;
 
+        let mut paths = vec![];
         for path in config.order {
-            let name = path.file_stem().unwrap().to_string_lossy().to_string();
-            let path = project.path.join(path);
-            if path.is_dir() {
-                // TODO: Sub directories
+            check_folder(&path)?;
+            if project.path.join(&path).is_dir() {
+                // Files of a folder listed by itself go in order of their names
+                project.add_folders(Some(&path));
+                paths.extend(markdown_files(&project.path, &path)?);
             } else {
-                let src = fs::read_to_string(&path)?;
-                let id = project.new_file(name.to_string(), src);
+                paths.push(path);
             }
         }
+        for path in config.folders {
+            project.add_folders(Some(&path));
+        }
+        for path in asset::asset_files(&project.path, Path::new(""))? {
+            project.add_folders(path.parent());
+            project.assets.insert(path);
+        }
+        for path in paths {
+            if project.files.values().any(|file| file.path == path) {
+                continue;
+            }
+            let src = fs::read_to_string(project.path.join(&path))?;
+            project.new_file(path, src);
+        }
+        project.expand_includes();
+        project.update_definitions();
+        project.link();
 
-        // let pdf_path_dir = PathBuf::from("./");
-        // let pdf_path_dir = pdf_path_dir.canonicalize().unwrap();
-        // let pdf_path = pdf_path_dir.join(format!("./project-{}.pdf", project.id.project_id));
+        Ok(project)
+    }
+    /// The project doc as pandoc parses it. Previews may use the faster native
+    /// backend, but exports keep all of pandoc's markdown.
+    fn export_doc(&self) -> io::Result<Doc> {
+        let mut docs = vec![];
+        for id in &self.order {
+            let local_doc = if self.backend == BackendKind::Pandoc {
+                self.files[id].local_doc.clone()
+            } else {
+                self.files[id].compile_with_pandoc()?
+            };
+            // Assets are relative to the folder of the file using them, but
+            // pandoc only knows the project directory
+            let mut resolve = ResolveAssets {
+                project: self,
+                file_id: *id,
+            };
+            let local_doc = walk_pandoc(&mut resolve, Pandoc(Meta(HashMap::new()), local_doc)).1;
+            docs.push(variables::substitute(&self.variables, local_doc).doc);
+        }
+        let linked: Vec<_> = crossref::link(outline::unique_ids(docs))
+            .into_iter()
+            .map(|linked| linked.doc)
+            .collect();
 
-        // ctx.wait(
-        //     project
-        //         .create_pdf(pdf_path)
-        //         .into_actor(&project)
-        //         .map(|_, _, _| ())
-        //         .map_err(|_, _, _| ()),
-        // );
+        let outline: Vec<_> = self
+            .order
+            .iter()
+            .zip(&linked)
+            .flat_map(|(id, doc)| outline::headings(*id, doc))
+            .collect();
+        let mut doc = vec![];
+        if self.metadata.toc && !outline.is_empty() {
+            doc.push(outline::toc(&outline));
+        }
+        doc.extend(linked.into_iter().flatten());
+        Ok(doc)
+    }
+    /// The project doc, ready to be exported.
+    pub fn prepare_export(
+        &self,
+        format: ExportFormat,
+        options: &ExportOptions,
+    ) -> io::Result<ExportInput> {
+        let project_dir = self.path.canonicalize()?;
 
-        Ok(project)
+        let doc = self.export_doc()?;
+
+        // Generated images live in the tmpdir of the file that produced them,
+        // and assets are relative to the project directory once resolved
+        let mut resource_path: Vec<PathBuf> = self
+            .order
+            .iter()
+            .map(|file_id| self.files[file_id].tmpdir.clone())
+            .collect();
+        resource_path.push(project_dir.clone());
+
+        let mut options = options.clone();
+        options.template = match options.template {
+            Some(template) => Some(resolve_template(&project_dir, &template)?),
+            None => None,
+        };
+
+        Ok(ExportInput {
+            pandoc: pandoc_types::definition::Pandoc(self.metadata.to_meta(), doc),
+            root: self.tmpdir.clone(),
+            resource_path,
+            format,
+            options,
+            settings: self.export_settings.resolve(&project_dir),
+            citations: self.citations.clone(),
+            project_dir,
+        })
+    }
+    /// Recompiles files whose inputs changed since their last compile,
+    /// returning whether any did.
+    pub fn recompile_stale(&mut self) -> bool {
+        let mut recompiled = false;
+        for file in self.files.values_mut() {
+            if file.inputs_changed() {
+                file.recompile();
+                recompiled = true;
+            }
+        }
+        recompiled
+    }
+    /// Replaces the source of a file, recompiling it along with every file
+    /// including it.
+    pub fn update_src(&mut self, file_id: FileId, src: String) {
+        if let Some(file) = self.files.get_mut(&file_id) {
+            file.src = src;
+        }
+        self.expand_includes();
+        self.update_definitions();
+    }
+    /// Expands the includes of every file, recompiling those whose expanded
+    /// source changed.
+    pub fn expand_includes(&mut self) {
+        let sources: HashMap<PathBuf, String> = self
+            .files
+            .values()
+            .map(|file| (file.path.clone(), file.src.clone()))
+            .collect();
+        for file in self.files.values_mut() {
+            let expanded = include::expand(&file.path, &sources);
+            file.include_diagnostics = expanded.diagnostics;
+            if expanded.src != file.expanded {
+                file.expanded = expanded.src;
+                file.compile();
+            }
+        }
+    }
+    /// Collects the link reference definitions of all files, recompiling
+    /// every file if they changed.
+    pub fn update_definitions(&mut self) {
+        let definitions: Vec<&str> = self
+            .order
+            .iter()
+            .flat_map(|id| crate::incremental::reference_definitions(&self.files[id].src))
+            .collect();
+        let definitions = definitions.join("\n");
+        if definitions == self.definitions {
+            return;
+        }
+        for file in self.files.values_mut() {
+            file.definitions = definitions.clone();
+            file.compile();
+        }
+        self.definitions = definitions;
     }
-    fn create_pdf(&self, pdf_path: PathBuf) -> io::Result<PathBuf> {
-        let root = &self.tmpdir;
+    /// Links the docs of all files together as one document, numbering
+    /// figures and the like across the whole project, and returns the edits
+    /// made to each doc and to the project doc.
+    pub fn link(&mut self) -> LinkEdits {
+        let substituted: Vec<_> = self
+            .order
+            .iter()
+            .map(|id| variables::substitute(&self.variables, self.files[id].local_doc.clone()))
+            .collect();
+        let mut undefined = vec![];
+        let mut docs = vec![];
+        for substituted in substituted {
+            undefined.push(substituted.undefined);
+            docs.push(substituted.doc);
+        }
+        let linked = crossref::link(outline::unique_ids(docs));
+
+        let mut edits = vec![];
+        for ((id, linked), undefined) in self.order.iter().zip(linked).zip(undefined) {
+            let file = self.files.get_mut(id).unwrap();
+            let unresolved = linked.unresolved.iter().map(|label| {
+                Diagnostic::warning_at(
+                    &file.src,
+                    &format!("@{}", label),
+                    format!("reference to unknown label {:?}", label),
+                )
+            });
+            let undefined = undefined.iter().map(|name| {
+                Diagnostic::warning_at(
+                    &file.src,
+                    &format!("{{{{{}}}}}", name),
+                    format!("variable {:?} is not defined", name),
+                )
+            });
+            file.link_diagnostics = unresolved.chain(undefined).collect();
+            edits.push((*id, file.set_doc(linked.doc)));
+        }
 
-        let doc: Vec<pandoc_types::definition::Block> = self
+        self.outline = self
             .order
             .iter()
-            // TODO: Dont clone here
-            .flat_map(|file_id| self.files[file_id].doc.iter().flat_map(|x| x))
-            .cloned()
+            .flat_map(|id| outline::headings(*id, self.files[id].doc.as_ref().unwrap()))
             .collect();
 
-        let meta = pandoc_types::definition::Meta(HashMap::new());
-        let doc = pandoc_types::definition::Pandoc(meta, doc);
-        crate::doc::to_pdf(&doc, &root, &pdf_path)?;
-        Ok(pdf_path)
+        let mut doc = vec![];
+        if self.metadata.toc && !self.outline.is_empty() {
+            doc.push(outline::toc(&self.outline));
+        }
+        let mut sections = vec![];
+        for id in &self.order {
+            let file_doc = self.files[id].doc.as_ref().unwrap();
+            sections.push(DocSection {
+                file: *id,
+                start: doc.len(),
+                len: file_doc.len(),
+            });
+            doc.extend(file_doc.iter().cloned());
+        }
+        let project = crate::diff::doc_edits(&self.doc, &doc);
+        self.doc = doc;
+        self.sections = sections;
+
+        LinkEdits {
+            files: edits,
+            project,
+        }
     }
+    /// Moves a file to `new_index` among the files of its folder.
     pub fn reorder_file(&mut self, file_id: FileId, new_index: usize) {
-        let old_index = self
+        let folder = self.files[&file_id].folder().to_path_buf();
+        let mut siblings: Vec<FileId> = self
             .order
+            .iter()
+            .filter(|id| self.files[id].folder() == folder)
+            .cloned()
+            .collect();
+        let old_index = siblings
             .iter()
             .position(|f| *f == file_id)
             .expect("file was in the order");
+        let new_index = new_index.min(siblings.len());
         if old_index == new_index {
             return;
         }
 
         if old_index > new_index {
-            self.order.remove(old_index);
-            self.order.insert(new_index, file_id);
+            siblings.remove(old_index);
+            siblings.insert(new_index, file_id);
+        } else {
+            siblings.insert(new_index, file_id);
+            siblings.remove(old_index);
+        }
+
+        // The files of other folders keep their places
+        let mut siblings = siblings.into_iter();
+        for id in self.order.iter_mut() {
+            if self.files[id].folder() == folder {
+                *id = siblings.next().unwrap();
+            }
+        }
+    }
+    /// Stores an asset at `path`, replacing the asset already there.
+    pub fn add_asset(&mut self, path: PathBuf, data: &[u8]) -> io::Result<()> {
+        if !asset::is_project_path(&path) || !asset::is_asset(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not an asset path", path.display()),
+            ));
+        }
+        let full_path = self.path.join(&path);
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(full_path, data)?;
+        self.add_folders(path.parent());
+        self.assets.insert(path);
+        Ok(())
+    }
+    /// Stores an uploaded file. Markdown becomes a project file, or the new
+    /// source of the one already at `path`, and anything else must be an
+    /// asset. Added markdown files are compiled once includes are expanded.
+    pub fn upload_file(&mut self, path: PathBuf, data: Vec<u8>) -> io::Result<Upload> {
+        if !asset::is_project_path(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is outside the project", path.display()),
+            ));
+        }
+        if path.extension().map_or(false, |ext| ext == "md") {
+            let src = String::from_utf8(data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let existing = self
+                .files
+                .values()
+                .find(|file| file.path == path)
+                .map(|file| file.id);
+            if let Some(id) = existing {
+                return Ok(Upload::Replaces(id, src));
+            }
+            let id = self.new_file(path, src);
+            self.files.get_mut(&id).unwrap().write_to_disk(&self.path)?;
+            Ok(Upload::Added(Some(id)))
+        } else {
+            self.add_asset(path, &data)?;
+            Ok(Upload::Added(None))
+        }
+    }
+    /// Picks up a change made outside of the server to the file or folder at
+    /// `path`, such as by another editor. Markdown files deleted from disk
+    /// are kept in the project, so nothing is lost when one is deleted by
+    /// accident.
+    pub fn reload_path(&mut self, path: &Path, from: Option<&Path>) -> io::Result<Reload> {
+        if !asset::is_project_path(path) {
+            return Ok(Reload::Unchanged);
+        }
+        let full_path = self.path.join(path);
+        if let Some(from) = from {
+            if self.rename_path(from, path) {
+                return Ok(Reload::Moved);
+            }
+        }
+        if full_path.is_dir() {
+            let folders = self.folders.len();
+            self.add_folders(Some(path));
+            return Ok(if self.folders.len() != folders {
+                Reload::Added
+            } else {
+                Reload::Unchanged
+            });
+        }
+
+        if path.extension().map_or(false, |ext| ext == "md") {
+            if !full_path.exists() {
+                return Ok(Reload::Unchanged);
+            }
+            let src = fs::read_to_string(&full_path)?;
+            let existing = self
+                .files
+                .values()
+                .find(|file| file.path == path)
+                .map(|file| file.id);
+            match existing {
+                // Files written by the server itself end up here
+                Some(id) if !self.files[&id].changed_on_disk(&src) => Ok(Reload::Unchanged),
+                Some(id) => {
+                    self.files.get_mut(&id).unwrap().read_from_disk(&src);
+                    Ok(Reload::Source(id, src))
+                }
+                None => {
+                    self.new_file(path.to_path_buf(), src);
+                    self.expand_includes();
+                    self.update_definitions();
+                    Ok(Reload::Added)
+                }
+            }
+        } else if asset::is_asset(path) {
+            let changed = if full_path.exists() {
+                self.add_folders(path.parent());
+                self.assets.insert(path.to_path_buf())
+            } else {
+                self.assets.remove(path)
+            };
+            Ok(if changed {
+                Reload::Added
+            } else {
+                Reload::Unchanged
+            })
+        } else {
+            Ok(Reload::Unchanged)
+        }
+    }
+    /// Moves the files, folders and assets at or below `from` to `to` after
+    /// they were renamed on disk, returning whether there were any. Without
+    /// this renamed files would be kept at their old paths and added again at
+    /// the new ones.
+    fn rename_path(&mut self, from: &Path, to: &Path) -> bool {
+        if !asset::is_project_path(from) {
+            return false;
+        }
+        let renamed = |path: &Path| {
+            let rest = path.strip_prefix(from).ok()?;
+            Some(if rest.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(rest)
+            })
+        };
+
+        let moved: Vec<(FileId, PathBuf)> = self
+            .files
+            .values()
+            .filter_map(|file| Some((file.id, renamed(&file.path)?)))
+            .collect();
+        // Files which stop being markdown, or would replace another file, are
+        // not followed
+        let followed = moved.iter().all(|(id, path)| {
+            path.extension().map_or(false, |ext| ext == "md")
+                && !self
+                    .files
+                    .values()
+                    .any(|file| file.id != *id && file.path == *path)
+        });
+        let folders: Vec<PathBuf> = self.folders.iter().filter_map(|p| renamed(p)).collect();
+        let assets: Vec<PathBuf> = self.assets.iter().filter_map(|p| renamed(p)).collect();
+        if !followed || (moved.is_empty() && folders.is_empty() && assets.is_empty()) {
+            return false;
+        }
+
+        for (id, path) in moved {
+            let file = self.files.get_mut(&id).unwrap();
+            file.name = path.file_stem().unwrap().to_string_lossy().to_string();
+            file.path = path;
+        }
+        self.folders.retain(|path| !path.starts_with(from));
+        self.assets.retain(|path| !path.starts_with(from));
+        self.assets.extend(assets);
+        for folder in folders {
+            self.add_folders(Some(&folder));
+        }
+        self.add_folders(to.parent());
+        true
+    }
+    /// The contents of the file or asset at `path`.
+    pub fn file_data(&self, path: &Path) -> Option<Vec<u8>> {
+        match self.files.values().find(|file| file.path == path) {
+            Some(file) => Some(file.src.clone().into_bytes()),
+            None => fs::read(self.asset_path(path)?).ok(),
+        }
+    }
+    /// Everything needed to recreate the project, for downloading it as an
+    /// archive. The cache and other generated files are left out.
+    pub fn archive_entries(&self) -> Vec<(PathBuf, archive::Entry)> {
+        let config = serde_json::to_string_pretty(&self.generate_config()).unwrap();
+        let mut entries = vec![(
+            PathBuf::from("config.json"),
+            archive::Entry::Data(config.into_bytes()),
+        )];
+        for id in &self.order {
+            let file = &self.files[id];
+            entries.push((
+                file.path.clone(),
+                archive::Entry::Data(file.src.clone().into_bytes()),
+            ));
+        }
+        for path in &self.assets {
+            entries.push((path.clone(), archive::Entry::File(self.path.join(path))));
+        }
+        entries
+    }
+    /// Where the asset at `path` is stored, if there is one.
+    pub fn asset_path(&self, path: &Path) -> Option<PathBuf> {
+        if self.assets.contains(path) {
+            Some(self.path.join(path))
         } else {
-            self.order.insert(new_index, file_id);
-            self.order.remove(old_index);
+            None
+        }
+    }
+    /// Resolves a path used in a file, such as the target of an image, to an
+    /// asset. Paths are relative to the folder of the file.
+    pub fn resolve_asset(&self, file_id: FileId, path: &Path) -> Option<PathBuf> {
+        self.relative_asset(file_id, path)
+            .map(|path| self.path.join(path))
+    }
+    /// Like `resolve_asset`, but relative to the project directory.
+    fn relative_asset(&self, file_id: FileId, path: &Path) -> Option<PathBuf> {
+        let mut resolved = self.files.get(&file_id)?.folder().to_path_buf();
+        for component in path.components() {
+            match component {
+                Component::Normal(c) => resolved.push(c),
+                Component::ParentDir => {
+                    // Assets are never outside the project directory
+                    if !resolved.pop() {
+                        return None;
+                    }
+                }
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+        Some(resolved).filter(|path| self.assets.contains(path))
+    }
+    /// Registers `folder` and the folders it is in.
+    fn add_folders(&mut self, folder: Option<&Path>) {
+        let mut folder = folder;
+        while let Some(path) = folder.filter(|path| !path.as_os_str().is_empty()) {
+            self.folders.insert(path.to_path_buf());
+            folder = path.parent();
+        }
+    }
+    pub fn create_folder(&mut self, path: PathBuf) -> io::Result<()> {
+        check_folder(&path)?;
+        fs::create_dir_all(self.path.join(&path))?;
+        self.add_folders(Some(&path));
+        Ok(())
+    }
+    /// Creates a file named `name` in `folder`, or in the project directory.
+    pub fn create_file(&mut self, name: &str, folder: Option<PathBuf>) -> io::Result<FileId> {
+        let is_name = !name.contains(|c| c == '/' || c == '\\')
+            && Path::new(name).components().count() == 1
+            && asset::is_project_path(Path::new(name));
+        if !is_name {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid file name {:?}", name),
+            ));
+        }
+        if let Some(folder) = &folder {
+            check_folder(folder)?;
+        }
+        let mut path = folder.unwrap_or_default().join(name);
+        if path.extension().is_none() {
+            path.set_extension("md");
+        }
+        if self.files.values().any(|file| file.path == path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+
+        let id = self.new_file(path, String::new());
+        self.files.get_mut(&id).unwrap().write_to_disk(&self.path)?;
+        Ok(id)
+    }
+    /// Moves a file into `folder`, or into the project directory, placing it
+    /// after the files already there.
+    pub fn move_file(&mut self, file_id: FileId, folder: Option<PathBuf>) -> io::Result<()> {
+        if let Some(folder) = &folder {
+            check_folder(folder)?;
+        }
+        let folder = folder.unwrap_or_default();
+        let file = &self.files[&file_id];
+        if file.folder() == folder {
+            return Ok(());
+        }
+        let path = folder.join(file.path.file_name().unwrap());
+        if self.files.values().any(|file| file.path == path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+
+        fs::create_dir_all(self.path.join(&folder))?;
+        let old_path = self.path.join(&file.path);
+        if old_path.exists() {
+            fs::rename(old_path, self.path.join(&path))?;
+        }
+        self.add_folders(Some(&folder));
+        self.files.get_mut(&file_id).unwrap().path = path;
+
+        self.order.retain(|id| *id != file_id);
+        let index = self
+            .order
+            .iter()
+            .rposition(|id| self.files[id].path.starts_with(&folder))
+            .map_or(self.order.len(), |i| i + 1);
+        self.order.insert(index, file_id);
+        Ok(())
+    }
+    /// The folders and files of the project, with files in the order they
+    /// appear in the document.
+    pub fn file_tree(&self) -> Vec<FileNode> {
+        self.folder_tree(Path::new(""))
+    }
+    fn folder_tree(&self, folder: &Path) -> Vec<FileNode> {
+        let mut nodes = vec![];
+        let mut seen = vec![];
+        let mut add_folder = |nodes: &mut Vec<FileNode>, path: PathBuf| {
+            if !seen.contains(&path) {
+                nodes.push(FileNode::Folder {
+                    name: path.file_name().unwrap().to_string_lossy().to_string(),
+                    children: self.folder_tree(&path),
+                    path: path.clone(),
+                });
+                seen.push(path);
+            }
+        };
+        for id in &self.order {
+            let file = &self.files[id];
+            if file.folder() == folder {
+                nodes.push(FileNode::File { id: *id });
+            } else if let Ok(rest) = file.path.strip_prefix(folder) {
+                // A folder appears where its first file does
+                let child = folder.join(rest.components().next().unwrap());
+                add_folder(&mut nodes, child);
+            }
+        }
+        for path in &self.folders {
+            if path.parent() == Some(folder) {
+                add_folder(&mut nodes, path.clone());
+            }
+        }
+        for path in &self.assets {
+            if path.parent() == Some(folder) {
+                nodes.push(FileNode::Asset {
+                    name: path.file_name().unwrap().to_string_lossy().to_string(),
+                    path: path.clone(),
+                });
+            }
+        }
+        nodes
+    }
+}
+
+/// An entry of the file tree of a project.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum FileNode {
+    File {
+        id: FileId,
+    },
+    Folder {
+        name: String,
+        path: PathBuf,
+        children: Vec<FileNode>,
+    },
+    Asset {
+        name: String,
+        path: PathBuf,
+    },
+}
+
+/// Rejects a folder from a client which would lead outside of the project.
+fn check_folder(folder: &Path) -> io::Result<()> {
+    if asset::is_project_path(folder) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is outside the project", folder.display()),
+        ))
+    }
+}
+
+/// Points the images of a file at the assets they use, relative to the
+/// project directory.
+struct ResolveAssets<'a> {
+    project: &'a Project,
+    file_id: FileId,
+}
+
+impl<'a> Walk for ResolveAssets<'a> {
+    fn inline(&mut self, inline: Inline) -> Vec<Inline> {
+        match inline {
+            Inline::Image(attr, alt, Target(url, title)) => {
+                let url = match self.project.relative_asset(self.file_id, Path::new(&url)) {
+                    Some(path) => path.to_string_lossy().to_string(),
+                    None => url,
+                };
+                vec![Inline::Image(attr, alt, Target(url, title))]
+            }
+            inline => vec![inline],
+        }
+    }
+}
+
+/// The path of a template from a client, which has to be a file inside of
+/// the project.
+fn resolve_template(project_dir: &Path, template: &Path) -> io::Result<PathBuf> {
+    check_folder(template)?;
+    let path = project_dir.join(template).canonicalize()?;
+    // Symlinks could still lead outside of the project
+    if !path.starts_with(project_dir) || !path.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("no template {} in the project", template.display()),
+        ));
+    }
+    Ok(path)
+}
+
+/// The markdown files in `folder` and below, sorted by path. Hidden
+/// directories, such as `.git`, are skipped.
+fn markdown_files(project_dir: &Path, folder: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(project_dir.join(folder))?
+        .map(|entry| entry.map(|entry| folder.join(entry.file_name())))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+
+    let mut files = vec![];
+    for path in entries {
+        let hidden = path
+            .file_name()
+            .map_or(false, |name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if project_dir.join(&path).is_dir() {
+            files.extend(markdown_files(project_dir, &path)?);
+        } else if path.extension().map_or(false, |ext| ext == "md") {
+            files.push(path);
         }
     }
+    Ok(files)
 }
 
 #[derive(Serialize, Clone)]
@@ -174,4 +971,189 @@ pub struct ProjectInfo {
     name: String,
     id: ProjectId,
     files: Vec<FileId>,
+    tree: Vec<FileNode>,
+    metadata: Metadata,
+    /// Whether the project keeps its history in git.
+    history: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    /// A project with the given files, compiled in process.
+    fn project(dir: &TempDir, paths: &[&str]) -> Project {
+        let mut project = Project::empty(
+            ProjectId { project_id: 0 },
+            "test".to_string(),
+            dir.path().join("project"),
+            dir.path().join("tmp"),
+        )
+        .unwrap();
+        project.backend = BackendKind::Native;
+        for path in paths {
+            project.new_file(PathBuf::from(path), String::new());
+        }
+        project
+    }
+
+    fn id(project: &Project, path: &str) -> FileId {
+        project
+            .files
+            .values()
+            .find(|file| file.path == Path::new(path))
+            .unwrap()
+            .id
+    }
+
+    fn order(project: &Project) -> Vec<PathBuf> {
+        project
+            .order
+            .iter()
+            .map(|id| project.files[id].path.clone())
+            .collect()
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn reorder_within_folder() {
+        let dir = TempDir::new("project").unwrap();
+        let mut project = project(&dir, &["a.md", "sub/b.md", "c.md", "sub/d.md"]);
+        let c = id(&project, "c.md");
+        project.reorder_file(c, 0);
+        assert_eq!(
+            order(&project),
+            paths(&["c.md", "sub/b.md", "a.md", "sub/d.md"])
+        );
+        let d = id(&project, "sub/d.md");
+        project.reorder_file(d, 0);
+        assert_eq!(
+            order(&project),
+            paths(&["c.md", "sub/d.md", "a.md", "sub/b.md"])
+        );
+        // Indices past the end of the folder move files to its end
+        project.reorder_file(c, 10);
+        assert_eq!(
+            order(&project),
+            paths(&["a.md", "sub/d.md", "c.md", "sub/b.md"])
+        );
+    }
+
+    #[test]
+    fn move_into_folder() {
+        let dir = TempDir::new("project").unwrap();
+        let mut project = project(&dir, &["a.md", "sub/b.md", "c.md"]);
+        let a = id(&project, "a.md");
+        project.move_file(a, Some(PathBuf::from("sub"))).unwrap();
+        // Moved files come after the files of their new folder
+        assert_eq!(order(&project), paths(&["sub/b.md", "sub/a.md", "c.md"]));
+
+        project.move_file(a, None).unwrap();
+        assert_eq!(order(&project), paths(&["sub/b.md", "c.md", "a.md"]));
+    }
+
+    #[test]
+    fn move_errors() {
+        let dir = TempDir::new("project").unwrap();
+        let mut project = project(&dir, &["a.md", "sub/a.md"]);
+        let a = id(&project, "a.md");
+        let error = project
+            .move_file(a, Some(PathBuf::from("sub")))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        let error = project
+            .move_file(a, Some(PathBuf::from("../out")))
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(order(&project), paths(&["a.md", "sub/a.md"]));
+    }
+
+    #[test]
+    fn file_tree() {
+        let dir = TempDir::new("project").unwrap();
+        let mut project = project(&dir, &["a.md", "sub/b.md", "c.md"]);
+        project.create_folder(PathBuf::from("empty")).unwrap();
+        project
+            .add_asset(PathBuf::from("plot.png"), b"png")
+            .unwrap();
+        let file = |path: &str| FileNode::File {
+            id: id(&project, path),
+        };
+        let folder = |path: &str, children: Vec<FileNode>| FileNode::Folder {
+            name: path.to_string(),
+            path: PathBuf::from(path),
+            children,
+        };
+        assert_eq!(
+            project.file_tree(),
+            vec![
+                file("a.md"),
+                folder("sub", vec![file("sub/b.md")]),
+                file("c.md"),
+                folder("empty", vec![]),
+                FileNode::Asset {
+                    name: "plot.png".to_string(),
+                    path: PathBuf::from("plot.png"),
+                },
+            ]
+        );
+    }
+
+    fn read_config(dir: &Path) -> ProjectConfig {
+        serde_json::from_str(&fs::read_to_string(dir.join("config.json")).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn import_orders_index_first() {
+        let dir = TempDir::new("import").unwrap();
+        for path in &[
+            "a.md",
+            "readme.md",
+            "notes.txt",
+            "sub/c.md",
+            "sub/index.md",
+            ".hidden/x.md",
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let id = ProjectId { project_id: 3 };
+        Project::prepare_import(dir.path(), id, "imported".to_string()).unwrap();
+        let config = read_config(dir.path());
+        assert_eq!(config.id, id);
+        assert_eq!(config.name, "imported");
+        assert_eq!(
+            config.order,
+            paths(&["readme.md", "a.md", "sub/c.md", "sub/index.md"])
+        );
+    }
+
+    #[test]
+    fn import_keeps_existing_config() {
+        let dir = TempDir::new("import").unwrap();
+        fs::write(dir.path().join("b.md"), "").unwrap();
+        fs::write(dir.path().join("a.md"), "").unwrap();
+        Project::prepare_import(dir.path(), ProjectId { project_id: 0 }, "old".to_string())
+            .unwrap();
+        let mut config = read_config(dir.path());
+        config.order.reverse();
+        fs::write(
+            dir.path().join("config.json"),
+            serde_json::to_string(&config).unwrap(),
+        )
+        .unwrap();
+
+        let id = ProjectId { project_id: 1 };
+        Project::prepare_import(dir.path(), id, "new".to_string()).unwrap();
+        let config = read_config(dir.path());
+        assert_eq!(config.id, id);
+        assert_eq!(config.name, "new");
+        assert_eq!(config.order, paths(&["b.md", "a.md"]));
+    }
 }
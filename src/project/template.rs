@@ -0,0 +1,60 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::export::ExportSettings;
+
+/// Built-in templates are directories in here. Their `template.json` holds the
+/// export settings, and every other file is copied into new projects.
+pub const BUILTIN_TEMPLATES_DIR: &str = "./templates";
+const SETTINGS_FILE: &str = "template.json";
+
+/// The names of all built-in templates.
+pub fn builtin_templates() -> io::Result<Vec<String>> {
+    let mut names = vec![];
+    for e in fs::read_dir(BUILTIN_TEMPLATES_DIR)? {
+        let path = e?.path();
+        if path.join(SETTINGS_FILE).is_file() {
+            names.push(path.file_name().unwrap().to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+fn template_dir(name: &str) -> io::Result<PathBuf> {
+    let dir = Path::new(BUILTIN_TEMPLATES_DIR).join(name);
+    if name.is_empty() || name.contains(|c: char| c == '/' || c == '\\' || c == '.') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid template name {:?}", name),
+        ));
+    }
+    if !dir.join(SETTINGS_FILE).is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no template named {:?}", name),
+        ));
+    }
+    Ok(dir)
+}
+
+/// Copies the files of the template `name` into `project_dir`, returning
+/// the export settings using them.
+pub fn apply(name: &str, project_dir: &Path) -> io::Result<ExportSettings> {
+    let dir = template_dir(name)?;
+
+    let settings = fs::read_to_string(dir.join(SETTINGS_FILE))?;
+    let settings: ExportSettings = serde_json::from_str(&settings)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    fs::create_dir_all(project_dir)?;
+    for e in fs::read_dir(&dir)? {
+        let path = e?.path();
+        if path.is_file() && path.file_name().unwrap() != SETTINGS_FILE {
+            fs::copy(&path, project_dir.join(path.file_name().unwrap()))?;
+        }
+    }
+
+    Ok(settings)
+}
@@ -8,22 +8,58 @@ use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::archive;
 use crate::client::{Client, ClientId};
-use crate::project::file::FileId;
-use crate::project::{Project, ProjectId, ProjectInfo};
+use crate::diff;
+use crate::edit_history::EditHistory;
+use crate::export::{self, ExportFormat, ExportInput, ExportOptions, JobEnd, JobId};
+use crate::history;
+use crate::project::file::{DocEdit, FileId};
+use crate::project::metadata::Metadata;
+use crate::project::template;
+use crate::project::{Project, ProjectId, ProjectInfo, Reload, Upload};
+use crate::snapshot::{self, SnapshotId};
 
 use crate::s2c::*;
 
 type ListenerMap = HashMap<ClientId, WeakAddr<Client>>;
 
+/// How often inputs of code blocks are checked for changes.
+const INPUT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How often changes are committed to the history of projects which keep
+/// one.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// How long the output of a completed export job can be downloaded.
+const EXPORT_JOB_TTL: Duration = Duration::from_secs(60 * 60);
+
 pub struct ProjectActor {
     pub project: Project,
     pub last_changed: SystemTime,
+    pub last_snapshot: SystemTime,
     pub project_listeners: ListenerMap,
+    pub project_doc_listeners: ListenerMap,
     pub file_src_listeners: HashMap<FileId, ListenerMap>,
     pub file_doc_listeners: HashMap<FileId, ListenerMap>,
+    pub edit_histories: HashMap<FileId, EditHistory>,
+    pub export_jobs: HashMap<JobId, ExportJob>,
+    job_counter: u64,
+    /// Numbers the outputs of exports over HTTP, which are not jobs.
+    http_export_counter: u64,
+}
+
+pub struct ExportJob {
+    /// Only the client which started a job can cancel it.
+    pub client_id: ClientId,
+    pub addr: WeakAddr<Client>,
+    pub format: ExportFormat,
+    pub cancel: Arc<AtomicBool>,
+    pub output_path: Option<PathBuf>,
+    pub done: bool,
 }
 
 impl ProjectActor {
@@ -31,27 +67,120 @@ impl ProjectActor {
         ProjectActor::create(move |ctx| ProjectActor {
             project,
             last_changed: SystemTime::now(),
+            last_snapshot: SystemTime::now(),
             project_listeners: HashMap::new(),
+            project_doc_listeners: HashMap::new(),
             file_src_listeners: HashMap::new(),
             file_doc_listeners: HashMap::new(),
+            edit_histories: HashMap::new(),
+            export_jobs: HashMap::new(),
+            job_counter: 0,
+            http_export_counter: 0,
         })
     }
-    pub fn new(id: ProjectId, name: String, path: PathBuf, tmpdir: PathBuf) -> Addr<ProjectActor> {
-        let mut project = Project::empty(id, name, path, tmpdir);
-        project.new_file("index.md".to_string(), "# Index".to_string());
-        project.new_file("abstract.md".to_string(), "# Abstract".to_string());
-        project.new_file("conlusion.md".to_string(), "# Conlusion".to_string());
+    pub fn new(
+        id: ProjectId,
+        name: String,
+        path: PathBuf,
+        tmpdir: PathBuf,
+        template: Option<String>,
+    ) -> io::Result<Addr<ProjectActor>> {
+        let mut project = Project::empty(id, name, path, tmpdir)?;
+        if let Some(template) = template {
+            project.export_settings = template::apply(&template, &project.path).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("failed to apply template {:?}: {}", template, e),
+                )
+            })?;
+        }
+        project.new_file("index.md".into(), "# Index".to_string());
+        project.new_file("abstract.md".into(), "# Abstract".to_string());
+        project.new_file("conlusion.md".into(), "# Conlusion".to_string());
+        project.expand_includes();
+        project.update_definitions();
+        project.link();
 
-        project
-            .write_to_disk(project.path.clone())
-            .expect("failed to write to disk");
+        project.write_to_disk(project.path.clone())?;
 
-        ProjectActor::new_(project)
+        Ok(ProjectActor::new_(project))
     }
-    pub fn read_from_disk(path: PathBuf, tmpdir: PathBuf) -> Addr<ProjectActor> {
-        let mut project = Project::read_from_disk(path, tmpdir).expect("failed to read from disk");
+    pub fn read_from_disk(path: PathBuf, tmpdir: PathBuf) -> io::Result<Addr<ProjectActor>> {
+        let project = Project::read_from_disk(path, tmpdir)?;
 
-        ProjectActor::new_(project)
+        Ok(ProjectActor::new_(project))
+    }
+    fn next_job_id(&mut self) -> JobId {
+        self.job_counter += 1;
+        JobId {
+            job_id: self.job_counter,
+        }
+    }
+    /// Where to write an export named `name`, such as `job-1`.
+    fn export_output_path(&self, name: &str, format: ExportFormat) -> Result<PathBuf, String> {
+        let export_dir = self.project.tmpdir.join("exports");
+        fs::create_dir_all(&export_dir).map_err(|e| e.to_string())?;
+        Ok(export_dir.join(format!("{}.{}", name, format.extension())))
+    }
+    fn send_job_update(&self, job: JobId, msg: Server2ClientProject) {
+        let addr = self
+            .export_jobs
+            .get(&job)
+            .and_then(|job| job.addr.upgrade());
+        if let Some(addr) = addr {
+            addr.do_send(Server2Client::Project {
+                id: self.project.id,
+                msg,
+            });
+        }
+    }
+    fn finish_job(&mut self, job: JobId, update: JobUpdate, ctx: &mut Context<Self>) {
+        let project_id = self.project.id;
+        let export_job = match self.export_jobs.get_mut(&job) {
+            Some(export_job) => export_job,
+            None => return,
+        };
+        let msg = match update {
+            JobUpdate::Complete => {
+                export_job.done = true;
+                Server2ClientProject::ExportComplete {
+                    job,
+                    format: export_job.format,
+                    url: format!("/jobs/{}/{}", project_id.project_id, job.job_id),
+                }
+            }
+            JobUpdate::Failed(error) => Server2ClientProject::ExportFailed { job, error },
+            JobUpdate::Cancelled => Server2ClientProject::ExportCancelled { job },
+            JobUpdate::Progress(_) => return,
+        };
+        let done = export_job.done;
+        self.send_job_update(job, msg);
+        // Only completed jobs are kept around for a while, so their output
+        // can be fetched
+        if done {
+            ctx.run_later(EXPORT_JOB_TTL, move |actor, _| actor.remove_job(job));
+        } else {
+            self.remove_job(job);
+        }
+    }
+    fn remove_job(&mut self, job: JobId) {
+        let output_path = self
+            .export_jobs
+            .remove(&job)
+            .and_then(|job| job.output_path);
+        if let Some(output_path) = output_path {
+            export::remove_output(&output_path);
+        }
+    }
+    fn notify_info(&self) {
+        let info = self.project.generate_info();
+
+        for l in self.project_listeners.values().filter_map(|f| f.upgrade()) {
+            l.do_send(Server2Client::Project {
+                id: self.project.id,
+                msg: Server2ClientProject::UpdateInfo { info: info.clone() },
+            });
+        }
     }
     fn build_update_event(&self, file_id: FileId, kind: ListenKind) -> Server2Client {
         let file = self.project.files.get(&file_id).unwrap();
@@ -60,7 +189,7 @@ impl ProjectActor {
                 src: file.src.clone(),
             },
             ListenKind::Doc => Server2ClientProjectFile::FileDoc {
-                doc: file.doc.clone().unwrap(),
+                doc: file.doc.clone().unwrap_or_default(),
             },
         };
 
@@ -71,7 +200,161 @@ impl ProjectActor {
     }
     fn notify(&mut self, file_id: FileId, kind: ListenKind, ignore_listener: Option<ClientId>) {
         let msg = self.build_update_event(file_id, kind);
-
+        self.send_to_listeners(file_id, kind, msg, ignore_listener);
+    }
+    /// Replaces the source of a file, keeping the old one to undo to.
+    fn edit_src(&mut self, file_id: FileId, src: String) {
+        if let Some(file) = self.project.files.get(&file_id) {
+            self.edit_histories
+                .entry(file_id)
+                .or_default()
+                .record(&file.src);
+        }
+        self.project.update_src(file_id, src);
+        self.last_changed = SystemTime::now();
+    }
+    /// Moves through the edit history of a file, sending the resulting source
+    /// to everyone, including whoever asked for it.
+    fn apply_history<F>(&mut self, file_id: FileId, f: F)
+    where
+        F: FnOnce(&mut EditHistory, String) -> Option<String>,
+    {
+        let current = match self.project.files.get(&file_id) {
+            Some(file) => file.src.clone(),
+            None => return,
+        };
+        let history = self.edit_histories.entry(file_id).or_default();
+        if let Some(src) = f(history, current) {
+            self.project.update_src(file_id, src);
+            self.last_changed = SystemTime::now();
+            self.notify(file_id, ListenKind::Src, None);
+            self.relink();
+        }
+    }
+    fn notify_doc_patch(&mut self, file_id: FileId, edits: Vec<DocEdit>) {
+        if edits.is_empty() {
+            return;
+        }
+        let msg = Server2Client::Project {
+            id: self.project.id,
+            msg: Server2ClientProject::File {
+                id: file_id,
+                msg: Server2ClientProjectFile::DocPatch { edits },
+            },
+        };
+        self.send_to_listeners(file_id, ListenKind::Doc, msg, None);
+    }
+    /// Links the project after files changed, and sends out what changed.
+    fn relink(&mut self) {
+        let old_sections = self.project.sections.clone();
+        let old_outline = self.project.outline.clone();
+        let edits = self.project.link();
+        for (file_id, edits) in edits.files {
+            self.notify_doc_patch(file_id, edits);
+            self.notify_diagnostics(file_id);
+        }
+        if !edits.project.is_empty() || old_sections != self.project.sections {
+            let msg = Server2Client::Project {
+                id: self.project.id,
+                msg: Server2ClientProject::ProjectDocPatch {
+                    edits: edits.project,
+                    sections: self.project.sections.clone(),
+                },
+            };
+            self.project_doc_listeners
+                .retain(|_, listener| listener.upgrade().is_some());
+            for listener in self.project_doc_listeners.values() {
+                if let Some(addr) = listener.upgrade() {
+                    addr.do_send(msg.clone());
+                }
+            }
+        }
+        if old_outline != self.project.outline {
+            self.notify_outline();
+        }
+    }
+    /// Sends the files and the file tree after files were added or moved.
+    fn notify_files(&self) {
+        let list = self.project.generate_file_info();
+        for l in self.project_listeners.values().filter_map(|f| f.upgrade()) {
+            l.do_send(Server2Client::Project {
+                id: self.project.id,
+                msg: Server2ClientProject::Files { list: list.clone() },
+            });
+        }
+        self.notify_info();
+    }
+    /// Writes the project to disk and commits it to its history, sending the
+    /// history to everyone in the project if anything changed.
+    fn commit(&mut self, message: &str) -> io::Result<()> {
+        self.project.write_to_disk(self.project.path.clone())?;
+        self.last_snapshot = SystemTime::now();
+        if history::commit(&self.project.path, message)?.is_some() {
+            let revisions = history::log(&self.project.path)?;
+            for l in self.project_listeners.values().filter_map(|f| f.upgrade()) {
+                l.do_send(Server2Client::Project {
+                    id: self.project.id,
+                    msg: Server2ClientProject::History {
+                        revisions: revisions.clone(),
+                    },
+                });
+            }
+        }
+        Ok(())
+    }
+    /// Tells a client that what it asked for failed.
+    fn request_failed(&self, addr: &Addr<Client>, error: String) {
+        addr.do_send(Server2Client::Error { error });
+    }
+    fn history_failed(&self, addr: &Addr<Client>, error: io::Error) {
+        addr.do_send(Server2Client::Project {
+            id: self.project.id,
+            msg: Server2ClientProject::HistoryFailed {
+                error: error.to_string(),
+            },
+        });
+    }
+    fn snapshot_failed(&self, addr: &Addr<Client>, error: io::Error) {
+        addr.do_send(Server2Client::Project {
+            id: self.project.id,
+            msg: Server2ClientProject::SnapshotFailed {
+                error: error.to_string(),
+            },
+        });
+    }
+    fn notify_outline(&self) {
+        for l in self.project_listeners.values().filter_map(|f| f.upgrade()) {
+            l.do_send(Server2Client::Project {
+                id: self.project.id,
+                msg: Server2ClientProject::Outline {
+                    outline: self.project.outline.clone(),
+                },
+            });
+        }
+    }
+    fn notify_diagnostics(&mut self, file_id: FileId) {
+        let diagnostics = match self.project.files.get_mut(&file_id) {
+            Some(file) => file.take_new_diagnostics(),
+            None => None,
+        };
+        if let Some(diagnostics) = diagnostics {
+            let msg = Server2Client::Project {
+                id: self.project.id,
+                msg: Server2ClientProject::File {
+                    id: file_id,
+                    msg: Server2ClientProjectFile::Diagnostics { diagnostics },
+                },
+            };
+            self.send_to_listeners(file_id, ListenKind::Src, msg, None);
+        }
+    }
+    fn send_to_listeners(
+        &mut self,
+        file_id: FileId,
+        kind: ListenKind,
+        msg: Server2Client,
+        ignore_listener: Option<ClientId>,
+    ) {
         let listernes = match kind {
             ListenKind::Src => self.file_src_listeners.get_mut(&file_id),
             ListenKind::Doc => self.file_doc_listeners.get_mut(&file_id),
@@ -80,7 +363,7 @@ impl ProjectActor {
         let listernes = if let Some(listernes) = listernes {
             listernes
         } else {
-            unimplemented!();
+            return;
         };
 
         let mut to_remove = vec![];
@@ -101,6 +384,21 @@ impl ProjectActor {
 
 impl Actor for ProjectActor {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(INPUT_POLL_INTERVAL, |act, _| {
+            if act.project.recompile_stale() {
+                act.relink();
+            }
+        });
+        ctx.run_interval(SNAPSHOT_INTERVAL, |act, _| {
+            if history::is_enabled(&act.project.path) && act.last_changed > act.last_snapshot {
+                if let Err(e) = act.commit("Automatic snapshot") {
+                    println!("failed to snapshot {:?}: {}", act.project.name, e);
+                }
+            }
+        });
+    }
 }
 
 pub struct GetInfo;
@@ -143,6 +441,12 @@ impl Handler<JoinProject> for ProjectActor {
             id: project_id,
             msg: Server2ClientProject::Files { list },
         });
+        join.addr.do_send(Server2Client::Project {
+            id: project_id,
+            msg: Server2ClientProject::Outline {
+                outline: self.project.outline.clone(),
+            },
+        });
     }
 }
 
@@ -162,6 +466,42 @@ impl Handler<LeaveProject> for ProjectActor {
     }
 }
 
+#[derive(Message)]
+pub struct JoinProjectDoc {
+    pub client_id: ClientId,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<JoinProjectDoc> for ProjectActor {
+    type Result = ();
+
+    fn handle(&mut self, join: JoinProjectDoc, _: &mut Self::Context) {
+        self.project_doc_listeners
+            .insert(join.client_id, join.addr.downgrade());
+
+        join.addr.do_send(Server2Client::Project {
+            id: self.project.id,
+            msg: Server2ClientProject::ProjectDoc {
+                doc: self.project.doc.clone(),
+                sections: self.project.sections.clone(),
+            },
+        });
+    }
+}
+
+#[derive(Message)]
+pub struct LeaveProjectDoc {
+    pub client_id: ClientId,
+}
+
+impl Handler<LeaveProjectDoc> for ProjectActor {
+    type Result = ();
+
+    fn handle(&mut self, leave: LeaveProjectDoc, _: &mut Self::Context) {
+        self.project_doc_listeners.remove(&leave.client_id);
+    }
+}
+
 pub struct ReorderFile {
     pub id: FileId,
     pub new_index: usize,
@@ -178,14 +518,129 @@ impl Handler<ReorderFile> for ProjectActor {
 
         self.last_changed = SystemTime::now();
 
-        let info = self.project.generate_info();
+        // Numbering and shared definitions depend on the order
+        self.project.update_definitions();
+        self.relink();
 
-        for l in self.project_listeners.values().filter_map(|f| f.upgrade()) {
-            l.do_send(Server2Client::Project {
-                id: self.project.id,
-                msg: Server2ClientProject::UpdateInfo { info: info.clone() },
-            });
+        self.notify_info();
+    }
+}
+
+#[derive(Message)]
+pub struct CreateFile {
+    pub name: String,
+    pub folder: Option<PathBuf>,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<CreateFile> for ProjectActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CreateFile, _: &mut Self::Context) {
+        if let Err(e) = self.project.create_file(&msg.name, msg.folder) {
+            self.request_failed(
+                &msg.addr,
+                format!("failed to create file {:?}: {}", msg.name, e),
+            );
+            return;
+        }
+        if let Err(e) = self.project.write_config(&self.project.path) {
+            self.request_failed(&msg.addr, format!("failed to write project config: {}", e));
+        }
+
+        self.last_changed = SystemTime::now();
+
+        // The file might be one others tried to include
+        self.project.expand_includes();
+        self.relink();
+
+        self.notify_files();
+    }
+}
+
+#[derive(Message)]
+pub struct CreateFolder {
+    pub path: PathBuf,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<CreateFolder> for ProjectActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CreateFolder, _: &mut Self::Context) {
+        if let Err(e) = self.project.create_folder(msg.path.clone()) {
+            self.request_failed(
+                &msg.addr,
+                format!("failed to create folder {:?}: {}", msg.path, e),
+            );
+            return;
+        }
+        if let Err(e) = self.project.write_config(&self.project.path) {
+            self.request_failed(&msg.addr, format!("failed to write project config: {}", e));
         }
+
+        self.notify_info();
+    }
+}
+
+#[derive(Message)]
+pub struct MoveFile {
+    pub id: FileId,
+    pub folder: Option<PathBuf>,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<MoveFile> for ProjectActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: MoveFile, _: &mut Self::Context) {
+        if !self.project.files.contains_key(&msg.id) {
+            return;
+        }
+        if let Err(e) = self.project.move_file(msg.id, msg.folder) {
+            self.request_failed(&msg.addr, format!("failed to move file: {}", e));
+            return;
+        }
+        if let Err(e) = self.project.write_config(&self.project.path) {
+            self.request_failed(&msg.addr, format!("failed to write project config: {}", e));
+        }
+
+        self.last_changed = SystemTime::now();
+
+        // Includes refer to files by path, and the order changed
+        self.project.expand_includes();
+        self.project.update_definitions();
+        self.relink();
+
+        self.notify_files();
+    }
+}
+
+pub struct UpdateMetadata {
+    pub metadata: Metadata,
+    pub addr: Addr<Client>,
+}
+impl Message for UpdateMetadata {
+    type Result = ();
+}
+
+impl Handler<UpdateMetadata> for ProjectActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateMetadata, _: &mut Self::Context) {
+        let old = std::mem::replace(&mut self.project.metadata, msg.metadata);
+        // The metadata only changes once it is stored
+        if let Err(e) = self.project.write_config(&self.project.path) {
+            self.project.metadata = old;
+            self.request_failed(&msg.addr, format!("failed to update metadata: {}", e));
+            return;
+        }
+
+        self.last_changed = SystemTime::now();
+
+        self.notify_info();
+        // The table of contents might have been turned on or off
+        self.relink();
     }
 }
 
@@ -216,6 +671,19 @@ impl Handler<JoinFile> for ProjectActor {
 
         msg.addr
             .do_send(self.build_update_event(msg.file_id, msg.kind));
+
+        if let ListenKind::Src = msg.kind {
+            let diagnostics = self.project.files[&msg.file_id].diagnostics();
+            if !diagnostics.is_empty() {
+                msg.addr.do_send(Server2Client::Project {
+                    id: self.project.id,
+                    msg: Server2ClientProject::File {
+                        id: msg.file_id,
+                        msg: Server2ClientProjectFile::Diagnostics { diagnostics },
+                    },
+                });
+            }
+        }
     }
 }
 
@@ -249,10 +717,636 @@ pub struct EditFile {
 impl Handler<EditFile> for ProjectActor {
     type Result = ();
     fn handle(&mut self, msg: EditFile, _: &mut Context<Self>) {
-        if let Some(file) = self.project.files.get_mut(&msg.file_id) {
-            file.update_src(msg.src);
+        if self.project.files.contains_key(&msg.file_id) {
+            self.edit_src(msg.file_id, msg.src);
             self.notify(msg.file_id, ListenKind::Src, Some(msg.ignore_listener));
-            self.notify(msg.file_id, ListenKind::Doc, None);
+            self.relink();
+        }
+    }
+}
+
+/// Starts keeping the history of the project in git.
+#[derive(Message)]
+pub struct EnableHistory {
+    pub addr: Addr<Client>,
+}
+
+impl Handler<EnableHistory> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: EnableHistory, _: &mut Context<Self>) {
+        if history::is_enabled(&self.project.path) {
+            return;
+        }
+        let res = history::init(&self.project.path).and_then(|_| self.commit("Start of history"));
+        if let Err(e) = res {
+            self.history_failed(&msg.addr, e);
+        }
+        self.notify_info();
+    }
+}
+
+/// Commits the project to its history with a message from the user.
+#[derive(Message)]
+pub struct Commit {
+    pub message: String,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<Commit> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: Commit, _: &mut Context<Self>) {
+        if let Err(e) = self.commit(&msg.message) {
+            self.history_failed(&msg.addr, e);
+        }
+    }
+}
+
+#[derive(Message)]
+pub struct GetHistory {
+    pub addr: Addr<Client>,
+}
+
+impl Handler<GetHistory> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: GetHistory, _: &mut Context<Self>) {
+        match history::log(&self.project.path) {
+            Ok(revisions) => msg.addr.do_send(Server2Client::Project {
+                id: self.project.id,
+                msg: Server2ClientProject::History { revisions },
+            }),
+            Err(e) => self.history_failed(&msg.addr, e),
+        }
+    }
+}
+
+/// Sends the source of a file as it was in a past revision.
+#[derive(Message)]
+pub struct GetRevision {
+    pub file_id: FileId,
+    pub revision: String,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<GetRevision> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: GetRevision, _: &mut Context<Self>) {
+        let file = match self.project.files.get(&msg.file_id) {
+            Some(file) => file,
+            None => return,
+        };
+        match history::show(&self.project.path, &msg.revision, &file.path) {
+            Ok(src) => msg.addr.do_send(Server2Client::Project {
+                id: self.project.id,
+                msg: Server2ClientProject::File {
+                    id: msg.file_id,
+                    msg: Server2ClientProjectFile::FileRevision {
+                        revision: msg.revision,
+                        src,
+                    },
+                },
+            }),
+            Err(e) => self.history_failed(&msg.addr, e),
+        }
+    }
+}
+
+/// Replaces the source of a file with the one in a past revision.
+#[derive(Message)]
+pub struct RestoreRevision {
+    pub file_id: FileId,
+    pub revision: String,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<RestoreRevision> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: RestoreRevision, _: &mut Context<Self>) {
+        let file = match self.project.files.get(&msg.file_id) {
+            Some(file) => file,
+            None => return,
+        };
+        let src = match history::show(&self.project.path, &msg.revision, &file.path) {
+            Ok(src) => src,
+            Err(e) => return self.history_failed(&msg.addr, e),
+        };
+        self.edit_src(msg.file_id, src);
+        self.notify(msg.file_id, ListenKind::Src, None);
+        self.relink();
+    }
+}
+
+#[derive(Message)]
+pub struct UndoEdit {
+    pub file_id: FileId,
+}
+
+impl Handler<UndoEdit> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: UndoEdit, _: &mut Context<Self>) {
+        self.apply_history(msg.file_id, |history, current| history.undo(current));
+    }
+}
+
+#[derive(Message)]
+pub struct RedoEdit {
+    pub file_id: FileId,
+}
+
+impl Handler<RedoEdit> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: RedoEdit, _: &mut Context<Self>) {
+        self.apply_history(msg.file_id, |history, current| history.redo(current));
+    }
+}
+
+/// Reverts a file to its source at some time.
+#[derive(Message)]
+pub struct RevertFile {
+    pub file_id: FileId,
+    /// Seconds since the unix epoch.
+    pub time: u64,
+}
+
+impl Handler<RevertFile> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: RevertFile, _: &mut Context<Self>) {
+        let time = UNIX_EPOCH + Duration::from_secs(msg.time);
+        self.apply_history(msg.file_id, |history, current| {
+            history.revert_to(time, current)
+        });
+    }
+}
+
+/// Keeps the current sources of the project under a name.
+#[derive(Message)]
+pub struct CreateSnapshot {
+    pub name: String,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<CreateSnapshot> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: CreateSnapshot, _: &mut Context<Self>) {
+        let files = self
+            .project
+            .files
+            .values()
+            .map(|file| (file.path.clone(), file.src.clone()))
+            .collect();
+        let list = snapshot::create(&self.project.path, msg.name, files)
+            .and_then(|_| snapshot::list(&self.project.path));
+        match list {
+            Ok(list) => {
+                for l in self.project_listeners.values().filter_map(|f| f.upgrade()) {
+                    l.do_send(Server2Client::Project {
+                        id: self.project.id,
+                        msg: Server2ClientProject::Snapshots { list: list.clone() },
+                    });
+                }
+            }
+            Err(e) => self.snapshot_failed(&msg.addr, e),
+        }
+    }
+}
+
+#[derive(Message)]
+pub struct GetSnapshots {
+    pub addr: Addr<Client>,
+}
+
+impl Handler<GetSnapshots> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: GetSnapshots, _: &mut Context<Self>) {
+        match snapshot::list(&self.project.path) {
+            Ok(list) => msg.addr.do_send(Server2Client::Project {
+                id: self.project.id,
+                msg: Server2ClientProject::Snapshots { list },
+            }),
+            Err(e) => self.snapshot_failed(&msg.addr, e),
+        }
+    }
+}
+
+/// Sends the changes to a file since a snapshot. Files added since are
+/// compared to an empty source.
+#[derive(Message)]
+pub struct DiffSnapshot {
+    pub file_id: FileId,
+    pub snapshot: SnapshotId,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<DiffSnapshot> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: DiffSnapshot, _: &mut Context<Self>) {
+        let file = match self.project.files.get(&msg.file_id) {
+            Some(file) => file,
+            None => return,
+        };
+        let snapshot = match snapshot::read(&self.project.path, msg.snapshot) {
+            Ok(snapshot) => snapshot,
+            Err(e) => return self.snapshot_failed(&msg.addr, e),
+        };
+        let old = snapshot.files.get(&file.path).map_or("", |src| &src[..]);
+        msg.addr.do_send(Server2Client::Project {
+            id: self.project.id,
+            msg: Server2ClientProject::File {
+                id: msg.file_id,
+                msg: Server2ClientProjectFile::SnapshotDiff {
+                    snapshot: msg.snapshot,
+                    hunks: diff::text_diff(old, &file.src),
+                },
+            },
+        });
+    }
+}
+
+/// Prepares an export to be rendered outside of the actor, returning the
+/// input along with where to write the output.
+pub struct PrepareExport {
+    pub format: ExportFormat,
+    pub options: ExportOptions,
+}
+
+impl Message for PrepareExport {
+    type Result = Result<(ExportInput, PathBuf), String>;
+}
+
+impl Handler<PrepareExport> for ProjectActor {
+    type Result = Result<(ExportInput, PathBuf), String>;
+    fn handle(
+        &mut self,
+        msg: PrepareExport,
+        _: &mut Context<Self>,
+    ) -> Result<(ExportInput, PathBuf), String> {
+        self.http_export_counter += 1;
+        let name = format!("http-{}", self.http_export_counter);
+        let output_path = self.export_output_path(&name, msg.format)?;
+        let input = self
+            .project
+            .prepare_export(msg.format, &msg.options)
+            .map_err(|e| e.to_string())?;
+        Ok((input, output_path))
+    }
+}
+
+#[derive(Message)]
+pub struct StartExport {
+    pub format: ExportFormat,
+    pub options: ExportOptions,
+    pub client_id: ClientId,
+    pub addr: Addr<Client>,
+}
+
+impl Handler<StartExport> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: StartExport, ctx: &mut Context<Self>) {
+        let job = self.next_job_id();
+        let format = msg.format;
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        self.export_jobs.insert(
+            job,
+            ExportJob {
+                client_id: msg.client_id,
+                addr: msg.addr.downgrade(),
+                format,
+                cancel: cancel.clone(),
+                output_path: None,
+                done: false,
+            },
+        );
+        self.send_job_update(job, Server2ClientProject::ExportStarted { job, format });
+
+        let prepared = self
+            .export_output_path(&format!("job-{}", job.job_id), format)
+            .and_then(|output_path| {
+                self.project
+                    .prepare_export(format, &msg.options)
+                    .map(|input| (input, output_path))
+                    .map_err(|e| e.to_string())
+            });
+        let (input, output_path) = match prepared {
+            Ok(prepared) => prepared,
+            Err(error) => {
+                self.finish_job(job, JobUpdate::Failed(error), ctx);
+                return;
+            }
+        };
+        if let Some(export_job) = self.export_jobs.get_mut(&job) {
+            export_job.output_path = Some(output_path.clone());
+        }
+
+        let addr = ctx.address();
+        thread::spawn(move || {
+            let res = export::run_job(&input, &output_path, &cancel, |elapsed| {
+                addr.do_send(ExportJobUpdate {
+                    job,
+                    update: JobUpdate::Progress(elapsed),
+                });
+            });
+            let update = match res {
+                Ok(JobEnd::Complete) => JobUpdate::Complete,
+                Ok(JobEnd::Cancelled) => JobUpdate::Cancelled,
+                Err(e) => JobUpdate::Failed(e.to_string()),
+            };
+            addr.do_send(ExportJobUpdate { job, update });
+        });
+    }
+}
+
+pub enum JobUpdate {
+    Progress(Duration),
+    Complete,
+    Failed(String),
+    Cancelled,
+}
+
+/// Sent by the thread running an export job.
+#[derive(Message)]
+pub struct ExportJobUpdate {
+    pub job: JobId,
+    pub update: JobUpdate,
+}
+
+impl Handler<ExportJobUpdate> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: ExportJobUpdate, ctx: &mut Context<Self>) {
+        match msg.update {
+            JobUpdate::Progress(elapsed) => self.send_job_update(
+                msg.job,
+                Server2ClientProject::ExportProgress {
+                    job: msg.job,
+                    elapsed_secs: elapsed.as_secs(),
+                },
+            ),
+            update => self.finish_job(msg.job, update, ctx),
+        }
+    }
+}
+
+#[derive(Message)]
+pub struct CancelExport {
+    pub job: JobId,
+    pub client_id: ClientId,
+}
+
+impl Handler<CancelExport> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: CancelExport, _: &mut Context<Self>) {
+        if let Some(job) = self.export_jobs.get(&msg.job) {
+            if job.client_id == msg.client_id {
+                job.cancel.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Looks up the output of a completed export job.
+pub struct GetExportJob {
+    pub job: JobId,
+}
+
+impl Message for GetExportJob {
+    type Result = Option<(PathBuf, ExportFormat)>;
+}
+
+impl Handler<GetExportJob> for ProjectActor {
+    type Result = Option<(PathBuf, ExportFormat)>;
+    fn handle(&mut self, msg: GetExportJob, _: &mut Context<Self>) -> Self::Result {
+        let job = self.export_jobs.get(&msg.job)?;
+        if job.done {
+            Some((job.output_path.clone()?, job.format))
+        } else {
+            None
+        }
+    }
+}
+
+/// Stores an uploaded asset in the project.
+pub struct AddAsset {
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+}
+
+impl Message for AddAsset {
+    type Result = Result<(), String>;
+}
+
+impl Handler<AddAsset> for ProjectActor {
+    type Result = Result<(), String>;
+    fn handle(&mut self, msg: AddAsset, _: &mut Context<Self>) -> Self::Result {
+        self.project
+            .add_asset(msg.path, &msg.data)
+            .map_err(|e| e.to_string())?;
+
+        self.last_changed = SystemTime::now();
+
+        self.notify_info();
+        Ok(())
+    }
+}
+
+/// Looks up where an asset is stored.
+pub struct GetAsset {
+    pub path: PathBuf,
+}
+
+impl Message for GetAsset {
+    type Result = Option<PathBuf>;
+}
+
+impl Handler<GetAsset> for ProjectActor {
+    type Result = Option<PathBuf>;
+    fn handle(&mut self, msg: GetAsset, _: &mut Context<Self>) -> Self::Result {
+        self.project.asset_path(&msg.path)
+    }
+}
+
+/// Looks up an asset by a path used in a file.
+pub struct ResolveAsset {
+    pub file_id: FileId,
+    pub path: PathBuf,
+}
+
+impl Message for ResolveAsset {
+    type Result = Option<PathBuf>;
+}
+
+impl Handler<ResolveAsset> for ProjectActor {
+    type Result = Option<PathBuf>;
+    fn handle(&mut self, msg: ResolveAsset, _: &mut Context<Self>) -> Self::Result {
+        self.project.resolve_asset(msg.file_id, &msg.path)
+    }
+}
+
+/// Stores files uploaded over HTTP, returning how many were stored.
+pub struct UploadFiles {
+    pub folder: Option<PathBuf>,
+    /// File names along with their contents.
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl Message for UploadFiles {
+    type Result = Result<usize, String>;
+}
+
+impl Handler<UploadFiles> for ProjectActor {
+    type Result = Result<usize, String>;
+    fn handle(&mut self, msg: UploadFiles, _: &mut Context<Self>) -> Self::Result {
+        let folder = msg.folder.unwrap_or_default();
+        let mut uploaded = vec![];
+        let mut count = 0;
+        let mut error = None;
+        for (name, data) in msg.files {
+            // Only the name is used, so uploads stay inside the folder
+            let path = match Path::new(&name).file_name() {
+                Some(file_name) => folder.join(file_name),
+                None => {
+                    error = Some(format!("invalid file name {:?}", name));
+                    break;
+                }
+            };
+            let stored = match self.project.upload_file(path, data) {
+                Ok(Upload::Added(id)) => {
+                    uploaded.extend(id);
+                    Ok(())
+                }
+                // Replacing a file can be undone like any other edit
+                Ok(Upload::Replaces(id, src)) => {
+                    self.edit_src(id, src);
+                    uploaded.push(id);
+                    self.project
+                        .files
+                        .get_mut(&id)
+                        .unwrap()
+                        .write_to_disk(&self.project.path)
+                }
+                Err(e) => Err(e),
+            };
+            match stored {
+                Ok(()) => count += 1,
+                Err(e) => {
+                    error = Some(format!("failed to store {:?}: {}", name, e));
+                    break;
+                }
+            }
+        }
+        if let Err(e) = self.project.write_config(&self.project.path) {
+            error = error.or_else(|| Some(format!("failed to write project config: {}", e)));
+        }
+
+        self.last_changed = SystemTime::now();
+
+        self.project.expand_includes();
+        self.project.update_definitions();
+        for id in uploaded {
+            self.notify(id, ListenKind::Src, None);
+        }
+        self.relink();
+
+        self.notify_files();
+        // Files stored before the error are kept
+        match error {
+            Some(e) => Err(e),
+            None => Ok(count),
+        }
+    }
+}
+
+/// Sent when a path in the project directory changed on disk.
+#[derive(Message)]
+pub struct ReloadPath {
+    /// Relative to the project directory.
+    pub path: PathBuf,
+    /// Where the path was renamed from, if it was renamed.
+    pub from: Option<PathBuf>,
+}
+
+impl Handler<ReloadPath> for ProjectActor {
+    type Result = ();
+    fn handle(&mut self, msg: ReloadPath, ctx: &mut Context<Self>) {
+        let reload = match self
+            .project
+            .reload_path(&msg.path, msg.from.as_ref().map(PathBuf::as_path))
+        {
+            Ok(reload) => reload,
+            Err(e) => {
+                println!("failed to reload {:?}: {}", msg.path, e);
+                return;
+            }
+        };
+        match (&reload, msg.from) {
+            // A renamed file may also have been changed before it was renamed
+            (Reload::Moved, _) => ctx.notify(ReloadPath {
+                path: msg.path,
+                from: None,
+            }),
+            // A rename which was not followed removed whatever was at its
+            // source
+            (_, Some(from)) => ctx.notify(ReloadPath {
+                path: from,
+                from: None,
+            }),
+            _ => {}
+        }
+        match reload {
+            Reload::Unchanged => return,
+            // Changes from outside can be undone like any other edit
+            Reload::Source(id, src) => {
+                self.edit_src(id, src);
+                self.notify(id, ListenKind::Src, None);
+                self.relink();
+            }
+            Reload::Added | Reload::Moved => {
+                // Nobody asked for the reload, so there is no one to tell
+                if let Err(e) = self.project.write_config(&self.project.path) {
+                    println!("failed to write project config: {}", e);
+                }
+                self.relink();
+                self.notify_files();
+            }
+        }
+        self.last_changed = SystemTime::now();
+    }
+}
+
+/// Looks up the contents of a file or asset by its path.
+pub struct GetFileData {
+    pub path: PathBuf,
+}
+
+impl Message for GetFileData {
+    type Result = Option<Vec<u8>>;
+}
+
+impl Handler<GetFileData> for ProjectActor {
+    type Result = Option<Vec<u8>>;
+    fn handle(&mut self, msg: GetFileData, _: &mut Context<Self>) -> Self::Result {
+        self.project.file_data(&msg.path)
+    }
+}
+
+/// Collects what goes into an archive of the project, which is written
+/// outside of the actor.
+pub struct GetArchive;
+
+impl Message for GetArchive {
+    type Result = ProjectArchive;
+}
+
+#[derive(MessageResponse)]
+pub struct ProjectArchive {
+    pub name: String,
+    pub entries: Vec<(PathBuf, archive::Entry)>,
+}
+
+impl Handler<GetArchive> for ProjectActor {
+    type Result = ProjectArchive;
+    fn handle(&mut self, _: GetArchive, _: &mut Context<Self>) -> Self::Result {
+        ProjectArchive {
+            name: self.project.name.clone(),
+            entries: self.project.archive_entries(),
         }
     }
 }
@@ -2,8 +2,14 @@ use actix::Message;
 
 use serde::Serialize;
 
-use crate::project::file::{Doc, FileId, FileInfo};
-use crate::project::{ProjectId, ProjectInfo};
+use crate::diagnostic::Diagnostic;
+use crate::diff::DiffHunk;
+use crate::export::{ExportFormat, JobId};
+use crate::history::Revision;
+use crate::outline::Heading;
+use crate::project::file::{Doc, DocEdit, FileId, FileInfo};
+use crate::project::{DocSection, ProjectId, ProjectInfo};
+use crate::snapshot::{SnapshotId, SnapshotInfo};
 
 #[derive(Serialize, Clone)]
 #[serde(tag = "type")]
@@ -19,10 +25,17 @@ pub enum Server2Client {
     Projects {
         list: Vec<ProjectInfo>,
     },
+    Templates {
+        list: Vec<String>,
+    },
     Project {
         id: ProjectId,
         msg: Server2ClientProject,
     },
+    /// A request of the client failed.
+    Error {
+        error: String,
+    },
 }
 
 #[derive(Serialize, Clone)]
@@ -34,6 +47,49 @@ pub enum Server2ClientProject {
     UpdateInfo {
         info: ProjectInfo,
     },
+    ProjectDoc {
+        doc: Doc,
+        sections: Vec<DocSection>,
+    },
+    ProjectDocPatch {
+        edits: Vec<DocEdit>,
+        sections: Vec<DocSection>,
+    },
+    Outline {
+        outline: Vec<Heading>,
+    },
+    ExportStarted {
+        job: JobId,
+        format: ExportFormat,
+    },
+    ExportProgress {
+        job: JobId,
+        elapsed_secs: u64,
+    },
+    ExportComplete {
+        job: JobId,
+        format: ExportFormat,
+        url: String,
+    },
+    ExportFailed {
+        job: JobId,
+        error: String,
+    },
+    ExportCancelled {
+        job: JobId,
+    },
+    History {
+        revisions: Vec<Revision>,
+    },
+    HistoryFailed {
+        error: String,
+    },
+    Snapshots {
+        list: Vec<SnapshotInfo>,
+    },
+    SnapshotFailed {
+        error: String,
+    },
     File {
         id: FileId,
         msg: Server2ClientProjectFile,
@@ -43,7 +99,27 @@ pub enum Server2ClientProject {
 #[derive(Serialize, Clone)]
 #[serde(tag = "type")]
 pub enum Server2ClientProjectFile {
-    FileLock { lock: Lock },
-    FileSource { src: String },
-    FileDoc { doc: Doc },
+    FileLock {
+        lock: Lock,
+    },
+    FileSource {
+        src: String,
+    },
+    FileDoc {
+        doc: Doc,
+    },
+    DocPatch {
+        edits: Vec<DocEdit>,
+    },
+    Diagnostics {
+        diagnostics: Vec<Diagnostic>,
+    },
+    FileRevision {
+        revision: String,
+        src: String,
+    },
+    SnapshotDiff {
+        snapshot: SnapshotId,
+        hunks: Vec<DiffHunk>,
+    },
 }
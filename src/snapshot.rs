@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SnapshotId {
+    pub snapshot_id: u64,
+}
+
+/// The sources of a project at some point, kept under a name such as
+/// "sent to client v1".
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    pub id: SnapshotId,
+    pub name: String,
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    /// The source of each file by its path.
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotInfo {
+    pub id: SnapshotId,
+    pub name: String,
+    pub time: u64,
+}
+
+/// Snapshots are kept next to the compile cache, out of the way of the
+/// project files and their history.
+fn snapshot_dir(project_dir: &Path) -> PathBuf {
+    project_dir.join(".riport").join("snapshots")
+}
+
+fn snapshot_path(project_dir: &Path, id: SnapshotId) -> PathBuf {
+    snapshot_dir(project_dir).join(format!("{}.json", id.snapshot_id))
+}
+
+/// The info of every snapshot, so listing them does not read all their
+/// sources.
+fn index_path(project_dir: &Path) -> PathBuf {
+    snapshot_dir(project_dir).join("index.json")
+}
+
+/// The snapshots of the project in `project_dir`, oldest first.
+pub fn list(project_dir: &Path) -> io::Result<Vec<SnapshotInfo>> {
+    let dir = snapshot_dir(project_dir);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    match fs::read_to_string(index_path(project_dir)) {
+        Ok(index) => {
+            serde_json::from_str(&index).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+        // Snapshots taken before there was an index
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            let list = rebuild_index(&dir)?;
+            write_index(project_dir, &list)?;
+            Ok(list)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn rebuild_index(dir: &Path) -> io::Result<Vec<SnapshotInfo>> {
+    let mut list = vec![];
+    for e in fs::read_dir(dir)? {
+        let path = e?.path();
+        let is_snapshot = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map_or(false, |stem| stem.parse::<u64>().is_ok());
+        if !is_snapshot {
+            continue;
+        }
+        let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        list.push(SnapshotInfo {
+            id: snapshot.id,
+            name: snapshot.name,
+            time: snapshot.time,
+        });
+    }
+    list.sort_by_key(|info| info.id.snapshot_id);
+    Ok(list)
+}
+
+fn write_index(project_dir: &Path, list: &[SnapshotInfo]) -> io::Result<()> {
+    fs::write(
+        index_path(project_dir),
+        serde_json::to_string(list).unwrap(),
+    )
+}
+
+pub fn read(project_dir: &Path, id: SnapshotId) -> io::Result<Snapshot> {
+    let snapshot = fs::read_to_string(snapshot_path(project_dir, id))?;
+    serde_json::from_str(&snapshot).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Stores the sources in `files` as a new snapshot named `name`.
+pub fn create(
+    project_dir: &Path,
+    name: String,
+    files: BTreeMap<PathBuf, String>,
+) -> io::Result<SnapshotInfo> {
+    let mut list = list(project_dir)?;
+    let id = SnapshotId {
+        snapshot_id: list.last().map_or(0, |info| info.id.snapshot_id + 1),
+    };
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let snapshot = Snapshot {
+        id,
+        name,
+        time,
+        files,
+    };
+
+    fs::create_dir_all(snapshot_dir(project_dir))?;
+    fs::write(
+        snapshot_path(project_dir, id),
+        serde_json::to_string(&snapshot).unwrap(),
+    )?;
+    let info = SnapshotInfo {
+        id,
+        name: snapshot.name,
+        time,
+    };
+    // The snapshot is only listed once it is stored
+    list.push(info.clone());
+    write_index(project_dir, &list)?;
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempdir::TempDir;
+
+    fn files(src: &str) -> BTreeMap<PathBuf, String> {
+        let mut files = BTreeMap::new();
+        files.insert(PathBuf::from("index.md"), src.to_string());
+        files
+    }
+
+    #[test]
+    fn create_list_and_read() {
+        let dir = TempDir::new("snapshots").unwrap();
+        assert_eq!(list(dir.path()).unwrap(), vec![]);
+        let first = create(dir.path(), "first".to_string(), files("a")).unwrap();
+        let second = create(dir.path(), "second".to_string(), files("b")).unwrap();
+        assert_eq!(first.id.snapshot_id, 0);
+        assert_eq!(second.id.snapshot_id, 1);
+        assert_eq!(list(dir.path()).unwrap(), vec![first, second.clone()]);
+        let snapshot = read(dir.path(), second.id).unwrap();
+        assert_eq!(snapshot.name, "second");
+        assert_eq!(snapshot.files, files("b"));
+    }
+
+    #[test]
+    fn index_is_rebuilt_from_snapshots() {
+        let dir = TempDir::new("snapshots").unwrap();
+        let first = create(dir.path(), "first".to_string(), files("a")).unwrap();
+        let second = create(dir.path(), "second".to_string(), files("b")).unwrap();
+        fs::remove_file(index_path(dir.path())).unwrap();
+        assert_eq!(list(dir.path()).unwrap(), vec![first, second]);
+        assert!(index_path(dir.path()).exists());
+    }
+}
@@ -0,0 +1,159 @@
+use pandoc_types::definition::{Inline, Meta, Pandoc};
+
+use std::collections::HashMap;
+
+use crate::project::file::Doc;
+use crate::walk_pandoc::{walk_pandoc, Walk};
+
+/// Replaces `{{name}}` in text with the value of the variable `name`.
+struct Substitute<'a> {
+    variables: &'a HashMap<String, String>,
+    undefined: Vec<String>,
+}
+
+impl<'a> Substitute<'a> {
+    fn text(&mut self, s: &str) -> String {
+        let mut out = String::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            let end = match rest[start..].find("}}") {
+                Some(end) => start + end,
+                None => break,
+            };
+            let name = &rest[start + 2..end];
+            if !is_name(name) {
+                // The next brace may start a variable, as in `{{{name}}`
+                out.push_str(&rest[..start + 1]);
+                rest = &rest[start + 1..];
+                continue;
+            }
+            out.push_str(&rest[..start]);
+            match self.variables.get(name) {
+                Some(value) => out.push_str(value),
+                // Left as is, so it stands out in the document
+                None => {
+                    out.push_str(&rest[start..end + 2]);
+                    if !self.undefined.iter().any(|u| u == name) {
+                        self.undefined.push(name.to_string());
+                    }
+                }
+            }
+            rest = &rest[end + 2..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+impl<'a> Walk for Substitute<'a> {
+    fn inline(&mut self, inline: Inline) -> Vec<Inline> {
+        match inline {
+            Inline::Str(s) if s.contains("{{") => vec![Inline::Str(self.text(&s))],
+            inline => vec![inline],
+        }
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// A doc after variables were substituted.
+pub struct Substituted {
+    pub doc: Doc,
+    /// Variables which were used, but are not defined.
+    pub undefined: Vec<String>,
+}
+
+pub fn substitute(variables: &HashMap<String, String>, doc: Doc) -> Substituted {
+    let mut substitute = Substitute {
+        variables,
+        undefined: vec![],
+    };
+    let doc = walk_pandoc(&mut substitute, Pandoc(Meta(HashMap::new()), doc)).1;
+    Substituted {
+        doc,
+        undefined: substitute.undefined,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pandoc_types::definition::Block;
+
+    fn str(s: &str) -> Inline {
+        Inline::Str(s.to_string())
+    }
+
+    fn variables() -> HashMap<String, String> {
+        let mut variables = HashMap::new();
+        variables.insert("client".to_string(), "ACME".to_string());
+        variables.insert("version".to_string(), "2.1".to_string());
+        variables
+    }
+
+    fn text(s: &str) -> (String, Vec<String>) {
+        let variables = variables();
+        let mut substitute = Substitute {
+            variables: &variables,
+            undefined: vec![],
+        };
+        (substitute.text(s), substitute.undefined)
+    }
+
+    #[test]
+    fn substitutes_defined_variables() {
+        assert_eq!(
+            text("{{client}}-v{{version}}."),
+            ("ACME-v2.1.".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn undefined_variables_are_kept_and_reported_once() {
+        assert_eq!(
+            text("{{missing}}/{{client}}/{{missing}}"),
+            (
+                "{{missing}}/ACME/{{missing}}".to_string(),
+                vec!["missing".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn only_names_are_variables() {
+        assert_eq!(
+            text("{{}} {{a b}} {{client"),
+            ("{{}} {{a b}} {{client".to_string(), vec![])
+        );
+        assert_eq!(text("{{{client}}"), ("{ACME".to_string(), vec![]));
+    }
+
+    #[test]
+    fn substitutes_in_doc() {
+        let doc = vec![Block::Para(vec![
+            str("For"),
+            Inline::Space,
+            Inline::Emph(vec![str("{{client}},")]),
+            Inline::Space,
+            str("{{date}}"),
+        ])];
+        let substituted = substitute(&variables(), doc);
+        assert_eq!(
+            substituted.doc,
+            vec![Block::Para(vec![
+                str("For"),
+                Inline::Space,
+                Inline::Emph(vec![str("ACME,")]),
+                Inline::Space,
+                str("{{date}}"),
+            ])]
+        );
+        assert_eq!(substituted.undefined, ["date"]);
+    }
+}
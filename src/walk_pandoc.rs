@@ -77,3 +77,28 @@ pub fn walk_pandoc(walker: &mut dyn Walk, pandoc: Pandoc) -> Pandoc {
             .collect(),
     )
 }
+
+/// The plain text of some inlines, like pandoc's `stringify`.
+pub fn stringify(inlines: &[Inline]) -> String {
+    let mut text = String::new();
+    for inline in inlines {
+        match inline {
+            Inline::Str(s) => text.push_str(s),
+            Inline::Code(_, s) | Inline::Math(_, s) => text.push_str(s),
+            Inline::Space | Inline::SoftBreak | Inline::LineBreak => text.push(' '),
+            Inline::Emph(inlines)
+            | Inline::Strong(inlines)
+            | Inline::Strikeout(inlines)
+            | Inline::Superscript(inlines)
+            | Inline::Subscript(inlines)
+            | Inline::SmallCaps(inlines)
+            | Inline::Quoted(_, inlines)
+            | Inline::Cite(_, inlines)
+            | Inline::Span(_, inlines)
+            | Inline::Link(_, inlines, _)
+            | Inline::Image(_, inlines, _) => text.push_str(&stringify(inlines)),
+            Inline::RawInline(_, _) | Inline::Note(_) => {}
+        }
+    }
+    text
+}
@@ -0,0 +1,80 @@
+use actix::*;
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How long changes to a path settle before they are reported, so a save
+/// which writes a file several times is only picked up once.
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+
+/// Sent when a path in the watched directory changed on disk.
+#[derive(Message)]
+pub struct PathChanged {
+    /// Relative to the watched directory.
+    pub path: PathBuf,
+    /// Where the path was renamed from, if it was renamed within the
+    /// watched directory.
+    pub from: Option<PathBuf>,
+}
+
+/// Changes to hidden files, such as the compile cache in `.riport`, a `.git`
+/// directory or swap files of editors, are left out.
+fn is_hidden(path: &Path) -> bool {
+    path.components().any(|c| match c {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+/// Watches `dir` and everything in it on a thread of its own, sending each
+/// path which changes to `recipient`.
+pub fn watch(dir: &Path, recipient: Recipient<PathChanged>) -> io::Result<()> {
+    // Paths in events are relative to the canonical directory
+    let dir = dir.canonicalize()?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::watcher(tx, DEBOUNCE_DELAY)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    watcher
+        .watch(&dir, RecursiveMode::Recursive)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+    thread::spawn(move || {
+        // The watcher stops when dropped, so it lives as long as the thread
+        let _watcher = watcher;
+        let relative = |path: &Path| match path.strip_prefix(&dir) {
+            Ok(path) if !is_hidden(path) => Some(path.to_path_buf()),
+            _ => None,
+        };
+        for event in rx {
+            let changed = match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path) => {
+                    relative(&path).map(|path| PathChanged { path, from: None })
+                }
+                // A file renamed from or to a path which is not watched, such
+                // as a swap file being saved over it, simply changed
+                DebouncedEvent::Rename(from, to) => match (relative(&from), relative(&to)) {
+                    (from, Some(path)) => Some(PathChanged { path, from }),
+                    (Some(path), None) => Some(PathChanged { path, from: None }),
+                    (None, None) => None,
+                },
+                DebouncedEvent::Error(e, path) => {
+                    println!("failed to watch {:?}: {}", path, e);
+                    continue;
+                }
+                _ => continue,
+            };
+            if let Some(changed) = changed {
+                if recipient.do_send(changed).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}